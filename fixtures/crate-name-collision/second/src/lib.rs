@@ -0,0 +1,13 @@
+pub fn sub(left: u64, right: u64) -> u64 {
+    left - right
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_works() {
+        assert_eq!(sub(4, 2), 2);
+    }
+}
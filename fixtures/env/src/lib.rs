@@ -0,0 +1,13 @@
+pub fn greeting() -> String {
+    std::env::var("GREETING").unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn greeting_is_set() {
+        assert_eq!(greeting(), "hello");
+    }
+}
@@ -0,0 +1,24 @@
+pub fn add(left: u64, right: u64) -> u64 {
+    left + right
+}
+
+#[cfg(feature = "extra")]
+pub fn sub(left: u64, right: u64) -> u64 {
+    left - right
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_works() {
+        assert_eq!(add(2, 2), 4);
+    }
+
+    #[cfg(feature = "extra")]
+    #[test]
+    fn sub_works() {
+        assert_eq!(sub(4, 2), 2);
+    }
+}
@@ -0,0 +1,23 @@
+pub fn add(left: u64, right: u64) -> u64 {
+    left + right
+}
+
+pub fn mul(left: u64, right: u64) -> u64 {
+    left * right
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_works() {
+        assert_eq!(add(2, 2), 4);
+    }
+
+    #[test]
+    #[ignore = "slow"]
+    fn mul_works() {
+        assert_eq!(mul(2, 3), 6);
+    }
+}
@@ -0,0 +1,79 @@
+use crate::{db, output, range_set::RangeSet, PackageCrateMap, PathCoverageMap, Test};
+use anyhow::Result;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    env::current_dir,
+    fmt::Write,
+    path::Path,
+};
+
+type TestCoverageMap = BTreeMap<(String, String, Test), PathCoverageMap>;
+
+/// Compares line-test.db to the database at `baseline`, and reports tests that were added or
+/// removed, or whose coverage changed.
+pub(crate) fn compare(baseline: &Path) -> Result<()> {
+    let current = flatten(db::read()?.coverage_map()?);
+    let baseline = flatten(db::read_from(baseline, &current_dir()?)?.coverage_map()?);
+
+    let keys = current
+        .keys()
+        .chain(baseline.keys())
+        .collect::<BTreeSet<_>>();
+
+    for key @ (package, krate, test) in keys {
+        match (baseline.get(key), current.get(key)) {
+            (None, Some(_)) => output::println(&format!("added: {package} {krate} {test}"))?,
+            (Some(_), None) => output::println(&format!("removed: {package} {krate} {test}"))?,
+            (Some(before), Some(after)) => {
+                let changes = coverage_changes(before, after);
+                if changes.is_empty() {
+                    continue;
+                }
+                output::println(&format!("changed: {package} {krate} {test}"))?;
+                for change in changes {
+                    output::println(&format!("    {change}"))?;
+                }
+            }
+            (None, None) => unreachable!(),
+        }
+    }
+
+    Ok(())
+}
+
+fn flatten(coverage_map: PackageCrateMap<BTreeMap<Test, PathCoverageMap>>) -> TestCoverageMap {
+    let mut test_coverage_map = TestCoverageMap::new();
+    for (package, coverage_map) in coverage_map {
+        for (krate, coverage_map) in coverage_map {
+            for (test, path_coverage_map) in coverage_map {
+                test_coverage_map.insert((package.clone(), krate.clone(), test), path_coverage_map);
+            }
+        }
+    }
+    test_coverage_map
+}
+
+/// Returns one line per path whose coverage differs, e.g., `src/lib.rs: +4-5 -1`.
+fn coverage_changes(before: &PathCoverageMap, after: &PathCoverageMap) -> Vec<String> {
+    let empty = HashSet::new();
+    let paths = before.keys().chain(after.keys()).collect::<BTreeSet<_>>();
+    let mut changes = Vec::new();
+    for path in paths {
+        let before = before.get(path).unwrap_or(&empty);
+        let after = after.get(path).unwrap_or(&empty);
+        let added = after.difference(before).copied().collect::<RangeSet<_>>();
+        let removed = before.difference(after).copied().collect::<RangeSet<_>>();
+        if added.is_empty() && removed.is_empty() {
+            continue;
+        }
+        let mut change = format!("{path}:");
+        if !added.is_empty() {
+            write!(change, " +{added}").unwrap();
+        }
+        if !removed.is_empty() {
+            write!(change, " -{removed}").unwrap();
+        }
+        changes.push(change);
+    }
+    changes
+}
@@ -0,0 +1,90 @@
+use crate::{parse_group, warn, PackageCrateMap, PathBranchMap, RangeSet, Test};
+use anyhow::{anyhow, Result};
+use std::{collections::BTreeMap, fmt::Write};
+
+/// Maps each path to the lines whose branches were specified, and each such line to the specified
+/// branches.
+pub(crate) type PathLineBranchMap = BTreeMap<String, BTreeMap<u32, RangeSet<u32>>>;
+
+pub(crate) fn parse_branch_specifications(specs: &[String]) -> Result<PathLineBranchMap> {
+    let mut path_line_branch_map = PathLineBranchMap::new();
+    for spec in specs {
+        let (path_line, branches) = spec
+            .rsplit_once(':')
+            .ok_or_else(|| anyhow!("branch specification does not contain `:`: {spec}"))?;
+        let (path, line) = path_line
+            .rsplit_once(':')
+            .ok_or_else(|| anyhow!("branch specification does not contain a line: {spec}"))?;
+        let line = line.parse::<u32>()?;
+        let branch_set = path_line_branch_map
+            .entry(path.to_owned())
+            .or_default()
+            .entry(line)
+            .or_default();
+        for range in parse_group(branches)? {
+            branch_set.insert_range(range);
+        }
+    }
+    Ok(path_line_branch_map)
+}
+
+/// Returns the tests that take branches in `path_line_branch_map`, along with the branches that no
+/// test takes.
+pub(crate) fn tests_for_branches(
+    branch_coverage_map: &PackageCrateMap<BTreeMap<Test, PathBranchMap>>,
+    path_line_branch_map: &PathLineBranchMap,
+) -> (PackageCrateMap<Vec<Test>>, PathLineBranchMap) {
+    let mut untaken = path_line_branch_map.clone();
+    let mut test_map = PackageCrateMap::<Vec<Test>>::default();
+    for (package, branch_coverage_map) in branch_coverage_map {
+        let test_map = test_map.entry(package.clone()).or_default();
+        for (krate, branch_coverage_map) in branch_coverage_map {
+            let test_map = test_map.entry(krate.clone()).or_default();
+            for (test, path_branch_map) in branch_coverage_map {
+                let mut added = false;
+                for (path, coverage) in path_branch_map {
+                    let Some(line_branch_map) = path_line_branch_map.get(path) else {
+                        continue;
+                    };
+                    let untaken = untaken.get_mut(path).unwrap();
+                    for &(line, branch) in coverage {
+                        let Some(branch_set) = line_branch_map.get(&line) else {
+                            continue;
+                        };
+                        if !branch_set.contains(branch) {
+                            continue;
+                        }
+                        untaken.get_mut(&line).unwrap().remove(branch);
+                        if !added {
+                            test_map.push(test.clone());
+                            added = true;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    (test_map, untaken)
+}
+
+pub(crate) fn warn_about_untaken_branches(path_line_branch_map: &PathLineBranchMap) -> Result<()> {
+    if path_line_branch_map
+        .values()
+        .flat_map(BTreeMap::values)
+        .all(RangeSet::is_empty)
+    {
+        return Ok(());
+    }
+
+    let mut msg = String::from("the following branches are not taken by any test:\n");
+
+    for (path, line_branch_map) in path_line_branch_map {
+        for (line, branch_set) in line_branch_map {
+            if !branch_set.is_empty() {
+                writeln!(msg, "    {path}:{line}:{branch_set}").unwrap();
+            }
+        }
+    }
+
+    warn(&msg)
+}
@@ -0,0 +1,200 @@
+use crate::{insert_function, warn, PathFunctionMap};
+use anyhow::{Context, Result};
+use proc_macro2::{LineColumn, Span};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::read_to_string,
+    path::Path,
+};
+use syn::{
+    spanned::Spanned,
+    visit::{self, Visit},
+    Ident, ImplItemFn, ItemFn, TraitItemFn,
+};
+use unidiff::PatchSet;
+
+// smoelius: The functions enclosing a diff's changes are found by parsing the changed files'
+// current contents with `syn`. This has limitations:
+// - Functions generated by macros, or written within macro invocations (e.g., within `macro_rules!`
+//   bodies or `quote!`), are not seen.
+// - `#[cfg(...)]` is not evaluated, so functions that are compiled out are found all the same.
+// - Functions are identified by name only, so a changed function can select tests that execute a
+//   like-named function elsewhere in the file.
+// - A file that fails to parse contributes no functions; its changed lines are still selected by
+//   line.
+
+/// Adds to `path_function_map` the functions enclosing the changes in `patch_set`.
+pub(crate) fn insert_changed_functions(
+    patch_set: &PatchSet,
+    path_function_map: &mut PathFunctionMap,
+) -> Result<()> {
+    for (path, lines) in changed_target_lines(patch_set) {
+        if Path::new(&path).extension().is_none_or(|ext| ext != "rs") {
+            continue;
+        }
+        let source = read_to_string(&path).with_context(|| format!("failed to read {path}"))?;
+        let functions = match enclosing_functions(&source, &lines) {
+            Ok(functions) => functions,
+            Err(error) => {
+                warn(&format!(
+                    "failed to parse {path}, so the functions its changes are within cannot be \
+                     determined: {error}"
+                ))?;
+                continue;
+            }
+        };
+        for function in functions {
+            insert_function(path_function_map, &path, &function);
+        }
+    }
+    Ok(())
+}
+
+/// Returns, for each file that `patch_set` changes, the lines of the file's new contents at which
+/// lines were added or removed.
+fn changed_target_lines(patch_set: &PatchSet) -> BTreeMap<String, BTreeSet<u32>> {
+    let mut path_lines_map = BTreeMap::<_, BTreeSet<_>>::new();
+    for patched_file in patch_set.files() {
+        let Some(path) = patched_file.target_file.strip_prefix("b/") else {
+            continue;
+        };
+        let lines = path_lines_map.entry(path.to_owned()).or_default();
+        for hunk in patched_file.hunks() {
+            // smoelius: A removed line has no line number in the new contents. It is attributed to
+            // the line that follows it.
+            let mut next = hunk.target_start;
+            for line in hunk.lines() {
+                if line.is_removed() {
+                    lines.extend(u32::try_from(next).ok());
+                    continue;
+                }
+                let Some(target_line_no) = line.target_line_no else {
+                    continue;
+                };
+                if line.is_added() {
+                    lines.extend(u32::try_from(target_line_no).ok());
+                }
+                next = target_line_no + 1;
+            }
+        }
+    }
+    path_lines_map
+}
+
+/// Returns the names of the functions in `source` that enclose any of `lines`.
+fn enclosing_functions(source: &str, lines: &BTreeSet<u32>) -> Result<BTreeSet<String>> {
+    let file = syn::parse_file(source)?;
+    let mut visitor = Visitor {
+        lines,
+        functions: BTreeSet::new(),
+    };
+    visitor.visit_file(&file);
+    Ok(visitor.functions)
+}
+
+struct Visitor<'a> {
+    lines: &'a BTreeSet<u32>,
+    functions: BTreeSet<String>,
+}
+
+impl Visitor<'_> {
+    fn check(&mut self, ident: &Ident, span: Span) {
+        let LineColumn { line: start, .. } = span.start();
+        let LineColumn { line: end, .. } = span.end();
+        let (Ok(start), Ok(end)) = (u32::try_from(start), u32::try_from(end)) else {
+            return;
+        };
+        if self.lines.range(start..=end).next().is_some() {
+            self.functions.insert(ident.to_string());
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for Visitor<'_> {
+    fn visit_item_fn(&mut self, item: &'ast ItemFn) {
+        self.check(&item.sig.ident, item.span());
+        visit::visit_item_fn(self, item);
+    }
+
+    fn visit_impl_item_fn(&mut self, item: &'ast ImplItemFn) {
+        self.check(&item.sig.ident, item.span());
+        visit::visit_impl_item_fn(self, item);
+    }
+
+    fn visit_trait_item_fn(&mut self, item: &'ast TraitItemFn) {
+        if item.default.is_some() {
+            self.check(&item.sig.ident, item.span());
+        }
+        visit::visit_trait_item_fn(self, item);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{changed_target_lines, enclosing_functions};
+    use std::collections::BTreeSet;
+    use unidiff::PatchSet;
+
+    const SOURCE: &str = "\
+fn one() {
+    let x = 1;
+    let y = 2;
+}
+
+struct S;
+
+impl S {
+    fn two(&self) {
+        fn nested() {}
+    }
+}
+
+trait T {
+    fn three() {}
+    fn four();
+}
+";
+
+    #[test]
+    fn functions_enclosing_lines() {
+        for (lines, expected) in [
+            (&[3][..], &["one"][..]),
+            (&[5, 6], &[]),
+            (&[10], &["nested", "two"]),
+            (&[9, 15], &["three", "two"]),
+            (&[16], &[]),
+        ] {
+            let lines = lines.iter().copied().collect::<BTreeSet<_>>();
+            let functions = enclosing_functions(SOURCE, &lines).unwrap();
+            assert_eq!(
+                expected.iter().copied().collect::<BTreeSet<_>>(),
+                functions.iter().map(String::as_str).collect(),
+                "{lines:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn removed_lines_are_attributed_to_the_following_line() {
+        let mut patch_set = PatchSet::new();
+        patch_set
+            .parse(
+                "\
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1,4 +1,3 @@
+ 1
+-2
++two
+ 3
+-4
+",
+            )
+            .unwrap();
+        let path_lines_map = changed_target_lines(&patch_set);
+        assert_eq!(
+            [2, 4].into_iter().collect::<BTreeSet<_>>(),
+            path_lines_map["src/lib.rs"]
+        );
+    }
+}
@@ -0,0 +1,303 @@
+use crate::{PackageCrateMap, PathCoverageMap, PathLineCountMap, PathLineMap, Test};
+use anyhow::{Context, Result};
+use clap::crate_version;
+use std::{
+    collections::BTreeMap,
+    env::current_dir,
+    fmt::Write,
+    fs::write,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Maps each path to a map from line numbers to the number of tests that cover that line.
+type PathHitsMap = BTreeMap<String, BTreeMap<u32, usize>>;
+
+/// Returns the hits for each line in `path_line_map`. Lines that no test covers have zero hits.
+pub(crate) fn hits_for_path_lines(
+    coverage_map: &PackageCrateMap<BTreeMap<Test, PathCoverageMap>>,
+    path_line_map: &PathLineMap,
+) -> PathHitsMap {
+    let mut path_hits_map = PathHitsMap::new();
+    for (path, line_set) in path_line_map {
+        let hits_map = path_hits_map.entry(path.clone()).or_default();
+        for range in line_set.clone() {
+            for line in range {
+                hits_map.insert(line, 0);
+            }
+        }
+    }
+    for path_coverage_map in test_coverage_maps(coverage_map) {
+        for (path, coverage) in path_coverage_map {
+            let Some(hits_map) = path_hits_map.get_mut(path) else {
+                continue;
+            };
+            for (line, hits) in hits_map.iter_mut() {
+                if coverage.contains(line) {
+                    *hits += 1;
+                }
+            }
+        }
+    }
+    path_hits_map
+}
+
+/// Returns the hits for each instrumented line in the database, i.e., each line with an lcov `DA`
+/// record in some test's coverage. Instrumented lines that no test executes have zero hits.
+pub(crate) fn hits_for_db(
+    line_count_map: &PackageCrateMap<BTreeMap<Test, PathLineCountMap>>,
+) -> PathHitsMap {
+    let mut path_hits_map = PathHitsMap::new();
+    for path_line_count_map in line_count_map
+        .values()
+        .flat_map(BTreeMap::values)
+        .flat_map(BTreeMap::values)
+    {
+        for (path, line_count_map) in path_line_count_map {
+            let hits_map = path_hits_map.entry(path.clone()).or_default();
+            for (&line, &count) in line_count_map {
+                *hits_map.entry(line).or_default() += usize::from(count != 0);
+            }
+        }
+    }
+    path_hits_map
+}
+
+fn test_coverage_maps(
+    coverage_map: &PackageCrateMap<BTreeMap<Test, PathCoverageMap>>,
+) -> impl Iterator<Item = &PathCoverageMap> {
+    coverage_map
+        .values()
+        .flat_map(BTreeMap::values)
+        .flat_map(BTreeMap::values)
+}
+
+/// Writes `path_hits_map` to `path` as Cobertura XML. Each directory becomes a package, and each
+/// file within a directory becomes a class.
+pub(crate) fn write_cobertura(path: &Path, path_hits_map: &PathHitsMap) -> Result<()> {
+    let mut dir_map = BTreeMap::<&str, Vec<(&str, &BTreeMap<u32, usize>)>>::new();
+    for (path, hits_map) in path_hits_map {
+        let dir = path.rsplit_once('/').map_or("", |(dir, _)| dir);
+        dir_map.entry(dir).or_default().push((path, hits_map));
+    }
+
+    let (covered, valid) = counts(path_hits_map.values());
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+    let mut xml = String::new();
+    writeln!(xml, r#"<?xml version="1.0" ?>"#)?;
+    writeln!(
+        xml,
+        r#"<!DOCTYPE coverage SYSTEM "http://cobertura.sourceforge.net/xml/coverage-04.dtd">"#
+    )?;
+    writeln!(
+        xml,
+        r#"<coverage line-rate="{}" branch-rate="0" lines-covered="{covered}" lines-valid="{valid}" branches-covered="0" branches-valid="0" complexity="0" version="cargo-line-test {}" timestamp="{timestamp}">"#,
+        rate(covered, valid),
+        crate_version!(),
+    )?;
+    writeln!(xml, "  <sources>")?;
+    writeln!(
+        xml,
+        "    <source>{}</source>",
+        escape(&current_dir()?.to_string_lossy())
+    )?;
+    writeln!(xml, "  </sources>")?;
+    writeln!(xml, "  <packages>")?;
+    for (dir, classes) in dir_map {
+        let (covered, valid) = counts(classes.iter().map(|&(_, hits_map)| hits_map));
+        writeln!(
+            xml,
+            r#"    <package name="{}" line-rate="{}" branch-rate="0" complexity="0">"#,
+            escape(dir),
+            rate(covered, valid)
+        )?;
+        writeln!(xml, "      <classes>")?;
+        for (path, hits_map) in classes {
+            let name = path.rsplit_once('/').map_or(path, |(_, name)| name);
+            let (covered, valid) = counts([hits_map]);
+            writeln!(
+                xml,
+                r#"        <class name="{}" filename="{}" line-rate="{}" branch-rate="0" complexity="0">"#,
+                escape(name),
+                escape(path),
+                rate(covered, valid)
+            )?;
+            writeln!(xml, "          <methods/>")?;
+            writeln!(xml, "          <lines>")?;
+            for (line, hits) in hits_map {
+                writeln!(xml, r#"            <line number="{line}" hits="{hits}"/>"#)?;
+            }
+            writeln!(xml, "          </lines>")?;
+            writeln!(xml, "        </class>")?;
+        }
+        writeln!(xml, "      </classes>")?;
+        writeln!(xml, "    </package>")?;
+    }
+    writeln!(xml, "  </packages>")?;
+    writeln!(xml, "</coverage>")?;
+
+    write(path, xml).with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// Returns the number of covered lines and the total number of lines.
+fn counts<'a>(hits_maps: impl IntoIterator<Item = &'a BTreeMap<u32, usize>>) -> (usize, usize) {
+    hits_maps
+        .into_iter()
+        .fold((0, 0), |(covered, valid), hits_map| {
+            (
+                covered + hits_map.values().filter(|&&hits| hits != 0).count(),
+                valid + hits_map.len(),
+            )
+        })
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn rate(covered: usize, valid: usize) -> f64 {
+    if valid == 0 {
+        1.0
+    } else {
+        covered as f64 / valid as f64
+    }
+}
+
+pub(crate) fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::{escape, hits_for_db, hits_for_path_lines, write_cobertura, PathHitsMap};
+    use crate::{PackageCrateMap, PathCoverageMap, PathLineCountMap, PathLineMap, Test};
+    use std::{collections::BTreeMap, fs::read_to_string};
+    use tempfile::tempdir;
+
+    #[test]
+    fn hits_count_covering_tests() {
+        let coverage_map = PackageCrateMap::<BTreeMap<Test, PathCoverageMap>>::from([(
+            String::from("package"),
+            BTreeMap::from([(
+                String::from("lib"),
+                BTreeMap::from([
+                    (
+                        "tests::a".parse().unwrap(),
+                        PathCoverageMap::from([(String::from("src/lib.rs"), [1, 2].into())]),
+                    ),
+                    (
+                        "tests::b".parse().unwrap(),
+                        PathCoverageMap::from([(String::from("src/lib.rs"), [2, 3].into())]),
+                    ),
+                ]),
+            )]),
+        )]);
+        let path_line_map = PathLineMap::from([(String::from("src/lib.rs"), (1..5).collect())]);
+        assert_eq!(
+            PathHitsMap::from([(
+                String::from("src/lib.rs"),
+                BTreeMap::from([(1, 1), (2, 2), (3, 1), (4, 0)])
+            )]),
+            hits_for_path_lines(&coverage_map, &path_line_map)
+        );
+    }
+
+    #[test]
+    fn db_hits_count_executing_tests() {
+        let line_count_map = PackageCrateMap::<BTreeMap<Test, PathLineCountMap>>::from([(
+            String::from("package"),
+            BTreeMap::from([(
+                String::from("lib"),
+                BTreeMap::from([
+                    (
+                        "tests::a".parse().unwrap(),
+                        PathLineCountMap::from([(
+                            String::from("src/lib.rs"),
+                            BTreeMap::from([(1, 1), (2, 1), (4, 0)]),
+                        )]),
+                    ),
+                    (
+                        "tests::b".parse().unwrap(),
+                        PathLineCountMap::from([(
+                            String::from("src/lib.rs"),
+                            BTreeMap::from([(2, 3), (3, 1), (4, 0)]),
+                        )]),
+                    ),
+                ]),
+            )]),
+        )]);
+        // smoelius: Line 4 is instrumented but executed by no test, so it has zero hits.
+        assert_eq!(
+            PathHitsMap::from([(
+                String::from("src/lib.rs"),
+                BTreeMap::from([(1, 1), (2, 2), (3, 1), (4, 0)])
+            )]),
+            hits_for_db(&line_count_map)
+        );
+    }
+
+    #[test]
+    fn line_rates_and_hits() {
+        let tempdir = tempdir().unwrap();
+        let path = tempdir.path().join("cobertura.xml");
+        let path_hits_map = PathHitsMap::from([
+            (
+                String::from("src/lib.rs"),
+                BTreeMap::from([(1, 1), (2, 2), (3, 1), (4, 0)]),
+            ),
+            (String::from("src/a&b.rs"), BTreeMap::from([(1, 0)])),
+        ]);
+        write_cobertura(&path, &path_hits_map).unwrap();
+
+        let xml = read_to_string(&path).unwrap();
+        assert!(
+            xml.contains(r#"lines-covered="3" lines-valid="5""#),
+            "{xml}"
+        );
+        assert!(xml.contains(r#"<coverage line-rate="0.6""#), "{xml}");
+        assert!(
+            xml.contains(r#"<package name="src" line-rate="0.6""#),
+            "{xml}"
+        );
+        assert!(
+            xml.contains(r#"<class name="lib.rs" filename="src/lib.rs" line-rate="0.75""#),
+            "{xml}"
+        );
+        assert!(
+            xml.contains(r#"<class name="a&amp;b.rs" filename="src/a&amp;b.rs" line-rate="0""#),
+            "{xml}"
+        );
+        let lines = xml
+            .lines()
+            .filter(|line| line.trim_start().starts_with("<line "))
+            .map(str::trim)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                r#"<line number="1" hits="0"/>"#,
+                r#"<line number="1" hits="1"/>"#,
+                r#"<line number="2" hits="2"/>"#,
+                r#"<line number="3" hits="1"/>"#,
+                r#"<line number="4" hits="0"/>"#,
+            ],
+            lines
+        );
+    }
+
+    #[test]
+    fn escapes_markup() {
+        assert_eq!(
+            "&lt;a href=&quot;x&quot;&gt;&amp;&apos;&lt;/a&gt;",
+            escape(r#"<a href="x">&'</a>"#)
+        );
+    }
+}
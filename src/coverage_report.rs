@@ -0,0 +1,56 @@
+use crate::{output, PathLineMap, RangeSet};
+use anyhow::Result;
+
+/// Prints, for each path in `path_line_map`, the percentage of its specified lines that some test
+/// covers, followed by the percentage over all paths. `uncovered` holds the lines that no test
+/// covers. If `instrumented` is `Some`, only the specified lines that it contains are counted.
+pub(crate) fn write_coverage_report(
+    path_line_map: &PathLineMap,
+    uncovered: &PathLineMap,
+    instrumented: Option<&PathLineMap>,
+) -> Result<()> {
+    let empty = RangeSet::default();
+    let restrict = |path: &String, line_set: &RangeSet<u32>| match instrumented {
+        Some(instrumented) => line_set.intersection(instrumented.get(path).unwrap_or(&empty)),
+        None => line_set.clone(),
+    };
+    let mut total_covered = 0;
+    let mut total_specified = 0;
+    for (path, line_set) in path_line_map {
+        let specified = restrict(path, line_set).len();
+        let covered = specified
+            - uncovered
+                .get(path)
+                .map_or(0, |line_set| restrict(path, line_set).len());
+        output::println(&format!("{path}: {}", percentage(covered, specified)))?;
+        total_covered += covered;
+        total_specified += specified;
+    }
+    output::println(&format!(
+        "total: {}",
+        percentage(total_covered, total_specified)
+    ))
+}
+
+#[allow(clippy::cast_precision_loss)]
+pub(crate) fn percentage(covered: usize, specified: usize) -> String {
+    let percent = if specified == 0 {
+        100.0
+    } else {
+        covered as f64 * 100.0 / specified as f64
+    };
+    format!("{percent:.2}% ({covered}/{specified})")
+}
+
+#[cfg(test)]
+mod test {
+    use super::percentage;
+
+    #[test]
+    fn percentages() {
+        assert_eq!("66.67% (4/6)", percentage(4, 6));
+        assert_eq!("0.00% (0/3)", percentage(0, 3));
+        // smoelius: No specified lines means nothing is left uncovered.
+        assert_eq!("100.00% (0/0)", percentage(0, 0));
+    }
+}
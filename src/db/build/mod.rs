@@ -1,18 +1,24 @@
-use super::read;
-use crate::{opts, run, util, warn, PackageCrateMap, Test, CTRLC};
+use super::{layout, read, Index, PathDigestMap};
+use crate::{
+    ignore, opts, output, progress::Progress, run, set_ctrlc_handler, util, warn, HashAlgo,
+    PackageCrateMap, Test,
+};
 use anyhow::{anyhow, bail, ensure, Context, Result};
-use cargo_metadata::MetadataCommand;
 use lcov::{Reader, Record};
+use serde::{Deserialize, Serialize};
 use std::{
     collections::{BTreeMap, BTreeSet},
     env::current_dir,
-    fs::{create_dir, write},
-    io::{BufRead, BufReader},
+    fs::{create_dir_all, metadata, read_to_string, write},
+    io::{BufRead, BufReader, Write},
     os::unix::ffi::OsStrExt,
     path::Path,
     process::{Command, Stdio},
-    sync::atomic::Ordering,
+    time::{Duration, Instant, SystemTime},
 };
+use tempfile::NamedTempFile;
+
+mod repeat;
 
 mod restorer;
 use restorer::Restorer;
@@ -21,52 +27,179 @@ const README: &str = "\
 This directory and its contents were automatically generated by cargo-line-test.
 ";
 
+/// The state of line-test.db when `--build` begins.
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum BuildState {
+    /// line-test.db does not exist.
+    Fresh,
+    /// line-test.db exists and is replaced. It is restored if the build fails.
+    Rebuild,
+    /// line-test.db exists, and only its missing coverage files are built (`--missing-only`).
+    MissingOnly,
+    /// line-test.db exists, and only the coverage of the packages owning the files changed by a
+    /// diff is rebuilt (`--packages-from-diff`). Those packages' coverage is restored if the build
+    /// fails.
+    Packages,
+}
+
 pub(crate) fn build() -> Result<()> {
-    let mut restorer = None;
-    let path = Path::new("line-test.db");
+    let path = layout::root();
 
     warn_if_db_not_ignored(path)?;
 
-    #[allow(clippy::collapsible_else_if)]
-    if path.try_exists()? {
-        if !opts::get().missing_only {
-            restorer = save_existing_db(path).map(Some)?;
+    let packages = if opts::get().packages_from_diff {
+        let packages = packages_from_diff()?;
+        if packages.is_empty() {
+            eprintln!("diff changes no files belonging to packages; nothing to build");
+            return Ok(());
         }
+        Some(packages)
     } else {
-        if opts::get().missing_only {
-            bail!("line-test.db does not exist");
+        None
+    };
+
+    let state = match (path.try_exists()?, opts::get().missing_only) {
+        (false, false) => BuildState::Fresh,
+        (true, false) if packages.is_some() => BuildState::Packages,
+        (true, false) => BuildState::Rebuild,
+        (true, true) => BuildState::MissingOnly,
+        (false, true) => {
+            bail!(
+                "{} does not exist; --missing-only requires an existing one",
+                path.display()
+            )
         }
     };
 
-    debug_assert_eq!(path.try_exists()?, opts::get().missing_only);
+    if opts::get().estimate {
+        return estimate(packages.as_ref(), state);
+    }
+
+    let mut restorer = if state == BuildState::Rebuild {
+        save_existing_db(path).map(Some)?
+    } else {
+        None
+    };
+
+    let mut package_restorers = Vec::new();
 
-    if !path.try_exists()? {
-        create_dir(path)?;
-        write(path.join("README.txt"), README)?;
+    if state == BuildState::Packages {
+        package_restorers = save_existing_packages(path, packages.as_ref().unwrap())?;
+    } else if state != BuildState::MissingOnly {
+        create_dir_all(path)?;
+        if !opts::get().no_db_readme {
+            let readme = opts::get().db_readme.as_deref().unwrap_or(README);
+            write(layout::readme_path(path), readme)?;
+        }
     }
 
-    let mut package_crate_test_map = package_crate_test_map()?;
+    let mut package_crate_test_map = package_crate_test_map(packages.as_ref())?;
 
-    if opts::get().missing_only {
+    if state == BuildState::MissingOnly {
         remove_tests_with_lcov(&mut package_crate_test_map)?;
     }
 
+    let start = Instant::now();
+
     run::run_tests(&package_crate_test_map, true)?;
 
-    build_digests()?;
+    let elapsed = start.elapsed();
 
-    if let Some(restorer) = restorer.as_mut() {
+    if let Some(repeat) = opts::get().repeat {
+        repeat::run_repeatedly(&package_crate_test_map, repeat)?;
+    }
+
+    build_digests(opts::get().hash_algo.unwrap_or_default())?;
+
+    let json = serde_json::to_string_pretty(&run::test_binary_args())?;
+    write(layout::test_args_path(path), json)?;
+
+    let tests = count_tests(&package_crate_test_map);
+    if tests != 0 {
+        let build_time = BuildTime {
+            tests,
+            millis: u64::try_from(elapsed.as_millis())?,
+        };
+        write(
+            layout::build_time_path(path),
+            serde_json::to_string_pretty(&build_time)?,
+        )?;
+    }
+
+    for restorer in restorer.iter_mut().chain(&mut package_restorers) {
         restorer.disable();
     }
 
     Ok(())
 }
 
+/// The time taken to run the tests of the most recent build. Only the test runs are timed, not,
+/// e.g., listing the tests or computing digests.
+#[derive(Deserialize, Serialize)]
+struct BuildTime {
+    tests: usize,
+    millis: u64,
+}
+
+/// Shows how many tests a build would run and, if a previous build's time was recorded, about how
+/// long the build would take. Nothing is run and line-test.db is not modified.
+fn estimate(packages: Option<&BTreeSet<String>>, state: BuildState) -> Result<()> {
+    let mut package_crate_test_map = package_crate_test_map(packages)?;
+
+    if state == BuildState::MissingOnly {
+        remove_tests_with_lcov(&mut package_crate_test_map)?;
+    }
+
+    let n = count_tests(&package_crate_test_map);
+    output::println(&format!(
+        "{n} test{} would be run",
+        if n == 1 { "" } else { "s" }
+    ))?;
+
+    let path = layout::build_time_path(layout::root());
+    if !path.try_exists()? {
+        output::println(
+            "No previous build time is recorded, so the build time cannot be estimated",
+        )?;
+        return Ok(());
+    }
+    let build_time = serde_json::from_str::<BuildTime>(&read_to_string(&path)?)
+        .with_context(|| format!("failed to parse {}", path.display()))?;
+
+    // smoelius: The estimate assumes every test takes the previous build's average time. The first
+    // test of each crate also pays for compiling the crate, so the estimate is rough.
+    let millis = u128::from(build_time.millis) * u128::try_from(n)?
+        / u128::try_from(build_time.tests.max(1))?;
+    output::println(&format!(
+        "Estimated build time: {} (the previous build ran {} tests in {})",
+        format_duration(Duration::from_millis(u64::try_from(millis)?)),
+        build_time.tests,
+        format_duration(Duration::from_millis(build_time.millis))
+    ))
+}
+
+fn count_tests(package_crate_test_map: &PackageCrateMap<Vec<Test>>) -> usize {
+    package_crate_test_map
+        .values()
+        .flat_map(BTreeMap::values)
+        .map(Vec::len)
+        .sum()
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, mins, secs) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if hours != 0 {
+        format!("{hours}h {mins}m {secs}s")
+    } else if mins != 0 {
+        format!("{mins}m {secs}s")
+    } else {
+        format!("{secs}s")
+    }
+}
+
 fn warn_if_db_not_ignored(path: &Path) -> Result<()> {
-    let mut command = Command::new("git");
-    command.args(["check-ignore", &path.to_string_lossy()]);
-    let status = command.status()?;
-    if !status.success() {
+    if !is_ignored_by_git(path)? {
         warn(&format!(
             "{} is not ignored by git, which may cause unnecessary recompilations",
             path.display(),
@@ -75,34 +208,104 @@ fn warn_if_db_not_ignored(path: &Path) -> Result<()> {
     Ok(())
 }
 
+// smoelius: `path` is passed to git as an `OsStr`, so it need not be valid UTF-8, and it is not
+// subject to shell interpretation. The `--` ensures it is not mistaken for an option. git is run in
+// `path`'s nearest existing ancestor so that `path` need not be within the current directory's
+// repository, and so that `path`'s parent need not exist.
+fn is_ignored_by_git(path: &Path) -> Result<bool> {
+    let mut command = Command::new("git");
+    command.args(["check-ignore", "--quiet", "--"]);
+    let ancestor = path
+        .ancestors()
+        .skip(1)
+        .filter(|ancestor| !ancestor.as_os_str().is_empty())
+        .find(|ancestor| ancestor.is_dir());
+    match ancestor.and_then(|ancestor| Some((ancestor, path.strip_prefix(ancestor).ok()?))) {
+        Some((ancestor, suffix)) => {
+            command.arg(suffix);
+            command.current_dir(ancestor);
+        }
+        None => {
+            command.arg(path);
+        }
+    }
+    let status = command.status()?;
+    Ok(status.success())
+}
+
 fn save_existing_db(path: &Path) -> Result<Restorer> {
     eprintln!("saving existing line-test.db; pressing ctrl-c will restore it");
 
-    ctrlc::set_handler(|| CTRLC.store(true, Ordering::SeqCst))?;
+    set_ctrlc_handler()?;
 
     Restorer::new(path)
 }
 
-fn package_crate_test_map() -> Result<PackageCrateMap<Vec<Test>>> {
+/// Returns the workspace's tests. If `packages` is `Some`, only the tests of those packages are
+/// returned.
+pub(super) fn package_crate_test_map(
+    packages: Option<&BTreeSet<String>>,
+) -> Result<PackageCrateMap<Vec<Test>>> {
     let package_crates = package_crates()?;
 
+    // smoelius: The number of tests is not known until every crate's tests have been listed, so a
+    // spinner is shown rather than a bar.
+    let spinner = if run::show_progress() && !opts::get().verbose {
+        Some(Progress::spinner())
+    } else {
+        None
+    };
+
     let mut test_map = PackageCrateMap::<Vec<Test>>::default();
     for (package, crates) in package_crates {
-        let test_map = test_map.entry(package.clone()).or_default();
+        if packages.is_some_and(|packages| !packages.contains(&package)) {
+            continue;
+        }
+        let mut crate_test_map = BTreeMap::new();
         for krate in crates.keys() {
+            if let Some(spinner) = spinner.as_ref() {
+                spinner.advance(&format!(
+                    "listing tests: package: {package}  crate: {krate}"
+                ))?;
+            }
             let tests = package_crate_tests(&package, krate)?;
-            test_map.insert(krate.clone(), tests);
+            if tests.is_empty() {
+                if opts::get().verbose {
+                    eprintln!("skipping package `{package}` crate `{krate}`: no tests");
+                }
+                continue;
+            }
+            crate_test_map.insert(krate.clone(), tests);
         }
+        if crate_test_map.is_empty() {
+            continue;
+        }
+        test_map.insert(package, crate_test_map);
+    }
+
+    if let Some(spinner) = spinner.as_ref() {
+        spinner.finish()?;
     }
 
     Ok(test_map)
 }
 
 fn package_crates() -> Result<PackageCrateMap<()>> {
-    let metadata = MetadataCommand::new().no_deps().exec()?;
+    let metadata = util::metadata_command().exec()?;
     let mut package_crates = PackageCrateMap::default();
     for package in metadata.packages {
         for target in package.targets {
+            // smoelius: Targets with `test = false` cannot be listed with `cargo test`, so do not
+            // spawn a subprocess for them.
+            if !target.test {
+                if opts::get().verbose {
+                    eprintln!(
+                        "skipping package `{}` target `{}`: `test = false`",
+                        package.name, target.name
+                    );
+                }
+                continue;
+            }
             let krate = if target.is_bin() {
                 Some(format!("bin:{}", target.name))
             } else if target.is_lib() {
@@ -128,13 +331,14 @@ fn package_crates() -> Result<PackageCrateMap<()>> {
 
 #[cfg_attr(dylint_lib = "supplementary", allow(commented_code))]
 fn package_crate_tests(package: &str, krate: &str) -> Result<Vec<Test>> {
-    let mut command = run::cargo_command(package, krate, None);
+    let mut command = run::cargo_command(package, krate, None, &["--list", "--format=terse"])?;
     // smoelius: For now, the outputs of the commands to build the tests are shown, which I think I
     // prefer.
     // command.arg("--quiet");
-    command.args(["--", "--list", "--format=terse"]);
     command.stdout(Stdio::piped());
-    let mut child = command.spawn()?;
+    let mut child = command
+        .spawn()
+        .with_context(|| format!("failed to spawn command: {command:?}"))?;
     let stdout = child
         .stdout
         .take()
@@ -152,22 +356,55 @@ fn package_crate_tests(package: &str, krate: &str) -> Result<Vec<Test>> {
     let status = child.wait()?;
     ensure!(status.success(), "command failed: {command:?}");
 
-    Ok(paths
-        .into_iter()
-        .map(|path| path.split("::").map(ToOwned::to_owned).collect())
-        .collect())
+    paths.iter().map(|path| path.parse()).collect()
+}
+
+/// Returns the packages owning the files changed by the diff read from standard input. A file is
+/// owned by the package whose manifest directory most closely contains it.
+fn packages_from_diff() -> Result<BTreeSet<String>> {
+    let path_line_map = crate::read_diff()?;
+    let metadata = util::metadata_command().exec()?;
+    let current_dir = current_dir()?;
+    let mut packages = BTreeSet::new();
+    for path in path_line_map.keys() {
+        let path_buf = current_dir.join(path);
+        if let Some(package) = util::owning_package(&metadata, &path_buf) {
+            packages.insert(package.name.clone());
+        } else if opts::get().verbose {
+            eprintln!("skipping `{path}`: not in any package");
+        }
+    }
+    Ok(packages)
+}
+
+/// Moves the coverage files of `packages` aside, so that they are rebuilt. The returned
+/// `Restorer`s move them back if the build fails.
+//
+// smoelius: The saved copies are kept directly beneath line-test.db rather than beside the package
+// directories, where they would be read as packages.
+fn save_existing_packages(path: &Path, packages: &BTreeSet<String>) -> Result<Vec<Restorer>> {
+    eprintln!("saving existing coverage of rebuilt packages; pressing ctrl-c will restore it");
+
+    set_ctrlc_handler()?;
+
+    let mut restorers = Vec::new();
+    for package in packages {
+        let path_buf = layout::packages_dir(path).join(package);
+        if path_buf.try_exists()? {
+            let restorer = Restorer::new_in(&path_buf, Some(path))
+                .with_context(|| format!("failed to save `{}`", path_buf.display()))?;
+            restorers.push(restorer);
+        }
+    }
+    Ok(restorers)
 }
 
 fn remove_tests_with_lcov(package_crate_test_map: &mut PackageCrateMap<Vec<Test>>) -> Result<()> {
-    let path = Path::new("line-test.db/packages");
     for (package, crate_test_map) in package_crate_test_map {
-        let path_buf = path.join(package);
         for (krate, tests) in crate_test_map {
-            let path_buf = path_buf.join(krate);
             let mut index = 0;
             while index < tests.len() {
-                let test = &tests[index];
-                let path_buf = path_buf.join(test.to_string()).with_extension("lcov");
+                let path_buf = layout::lcov_path(layout::root(), package, krate, &tests[index]);
                 if path_buf.try_exists()? {
                     tests.remove(index);
                 } else {
@@ -179,38 +416,161 @@ fn remove_tests_with_lcov(package_crate_test_map: &mut PackageCrateMap<Vec<Test>
     Ok(())
 }
 
-pub(crate) fn build_digests() -> Result<()> {
-    let package_crate_test_map = read::read_package_crate_test_map()?;
+pub(crate) fn build_digests(hash_algo: HashAlgo) -> Result<()> {
+    let package_crate_test_map = read::read_package_crate_test_map(layout::root())?;
+
+    let index = build_index(&package_crate_test_map)?;
 
-    let paths = collect_paths(&package_crate_test_map)?;
+    let paths = index
+        .values()
+        .flat_map(BTreeMap::values)
+        .flat_map(BTreeMap::values)
+        .flatten()
+        .cloned()
+        .collect::<BTreeSet<_>>();
+
+    let existing = existing_digests(hash_algo)?;
 
     let mut path_digest_map = BTreeMap::new();
     for path in paths {
-        let digest = util::hash_path_contents(&path)?;
+        // smoelius: A path could have been deleted since the coverage referring to it was built,
+        // e.g., if `--refresh` did not re-run a test that covered only deleted files.
+        if !Path::new(&path).try_exists()? {
+            continue;
+        }
+        let digest = match reusable_digest(existing.as_ref(), &path)? {
+            Some(digest) => digest,
+            None => util::hash_path_contents(&path, hash_algo)?,
+        };
         path_digest_map.insert(path, hex::encode(digest));
     }
 
     let json = serde_json::to_string_pretty(&path_digest_map)?;
-    write("line-test.db/digests.json", json)?;
+    write_atomically(&layout::digests_path(layout::root()), &json)?;
+
+    write_index(&index)?;
+
+    let mut manifest_digest_map = BTreeMap::new();
+    for path in manifest_paths()? {
+        let digest = util::hash_path_contents(&path, hash_algo)?;
+        manifest_digest_map.insert(path, hex::encode(digest));
+    }
+
+    let json = serde_json::to_string_pretty(&manifest_digest_map)?;
+    write(layout::manifest_digests_path(layout::root()), json)?;
+    write(
+        layout::hash_algo_path(layout::root()),
+        format!("{hash_algo}\n"),
+    )?;
 
     Ok(())
 }
 
-fn collect_paths(package_crate_test_map: &PackageCrateMap<Vec<Test>>) -> Result<BTreeSet<String>> {
-    let mut paths = BTreeSet::new();
+/// Returns the existing digests and when they were written, provided they were computed with
+/// `hash_algo`.
+fn existing_digests(hash_algo: HashAlgo) -> Result<Option<(PathDigestMap, SystemTime)>> {
+    let root = layout::root();
+    let path = layout::digests_path(root);
+    if !path.try_exists()? || read::read_hash_algo(root)? != hash_algo {
+        return Ok(None);
+    }
+    let modified = metadata(&path)?.modified()?;
+    let path_digest_map = read::read_digest_map(&path)?;
+    Ok(Some((path_digest_map, modified)))
+}
+
+// smoelius: A file last modified before the existing digests were written cannot have changed
+// since, so its existing digest can be reused. Hashing only the files that changed makes
+// `--refresh` fast on large repositories. A modification time equal to the digests' is treated as
+// a change, to be safe on file systems with coarse timestamps.
+fn reusable_digest(
+    existing: Option<&(PathDigestMap, SystemTime)>,
+    path: &str,
+) -> Result<Option<[u8; 32]>> {
+    let Some((path_digest_map, written)) = existing else {
+        return Ok(None);
+    };
+    let Some(digest) = path_digest_map.get(path) else {
+        return Ok(None);
+    };
+    if metadata(path)?.modified()? < *written {
+        Ok(Some(*digest))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Writes `contents` to `path` by way of a temporary file, so that a reader never observes a
+/// partially written file.
+fn write_atomically(path: &Path, contents: &str) -> Result<()> {
+    let dir = path
+        .parent()
+        .ok_or_else(|| anyhow!("`{}` has no parent", path.display()))?;
+    let mut tempfile = NamedTempFile::new_in(dir)?;
+    tempfile.write_all(contents.as_bytes())?;
+    tempfile.persist(path)?;
+    Ok(())
+}
+
+/// Returns the paths of the workspace's Cargo.lock (if any) and manifests, relative to the current
+/// directory where possible.
+fn manifest_paths() -> Result<BTreeSet<String>> {
+    let metadata = util::metadata_command().exec()?;
+    let current_dir = current_dir()?;
+    let mut paths = vec![metadata.workspace_root.join("Cargo.toml")];
+    let lock = metadata.workspace_root.join("Cargo.lock");
+    if lock.try_exists()? {
+        paths.push(lock);
+    }
+    paths.extend(
+        metadata
+            .packages
+            .into_iter()
+            .map(|package| package.manifest_path),
+    );
+    Ok(paths
+        .into_iter()
+        .map(|path| {
+            path.strip_prefix(&current_dir)
+                .map_or_else(|_| path.to_string(), ToString::to_string)
+        })
+        .collect())
+}
+
+/// Returns the paths each test covers, as recorded in the test's lcov file.
+fn build_index(package_crate_test_map: &PackageCrateMap<Vec<Test>>) -> Result<Index> {
+    let mut index = Index::default();
     for (package, crate_test_map) in package_crate_test_map {
+        let index = index.entry(package.clone()).or_default();
         for (krate, tests) in crate_test_map {
+            let index = index.entry(krate.clone()).or_default();
             for test in tests {
-                let path_buf = Path::new("line-test.db/packages")
-                    .join(package)
-                    .join(krate)
-                    .join(test.to_string())
-                    .with_extension("lcov");
+                let path_buf = layout::lcov_path(layout::root(), package, krate, test);
+                let mut paths = BTreeSet::new();
                 ingest_lcov_paths(&mut paths, &path_buf)?;
+                index.insert(test.clone(), paths);
             }
         }
     }
-    Ok(paths)
+    Ok(index)
+}
+
+fn write_index(index: &Index) -> Result<()> {
+    let mut value = serde_json::Map::new();
+    for (package, crate_index) in index {
+        let mut package_value = serde_json::Map::new();
+        for (krate, test_index) in crate_index {
+            let crate_value = test_index
+                .iter()
+                .map(|(test, paths)| (test.to_string(), serde_json::json!(paths)))
+                .collect();
+            package_value.insert(krate.clone(), serde_json::Value::Object(crate_value));
+        }
+        value.insert(package.clone(), serde_json::Value::Object(package_value));
+    }
+    let json = serde_json::to_string_pretty(&value)?;
+    write(layout::index_path(layout::root()), json)?;
+    Ok(())
 }
 
 #[allow(clippy::single_match)]
@@ -219,12 +579,44 @@ fn ingest_lcov_paths(paths: &mut BTreeSet<String>, path: &Path) -> Result<()> {
     for result in Reader::open_file(path)? {
         match result? {
             Record::SourceFile { path } => {
-                let path = path.strip_prefix(&current_dir)?;
+                // smoelius: A source file outside of the current directory keeps its absolute
+                // path (see `run::relativize_lcov`).
+                let path = path.strip_prefix(&current_dir).unwrap_or(&path);
                 let path_utf8 = String::from_utf8(path.as_os_str().as_bytes().to_owned())?;
-                paths.insert(path_utf8);
+                if !ignore::is_ignored(&path_utf8)? {
+                    paths.insert(path_utf8);
+                }
             }
             _ => {}
         }
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::is_ignored_by_git;
+    use std::{
+        fs::{create_dir, write},
+        process::Command,
+    };
+    use tempfile::tempdir;
+
+    #[test]
+    fn path_with_space_is_ignored() {
+        let tempdir = tempdir().unwrap();
+        let root = tempdir.path().canonicalize().unwrap();
+        let status = Command::new("git")
+            .args(["init", "--quiet"])
+            .arg(&root)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        write(root.join(".gitignore"), "/line test.db/\n").unwrap();
+        create_dir(root.join("line test.db")).unwrap();
+        create_dir(root.join("line-test.db")).unwrap();
+
+        assert!(is_ignored_by_git(&root.join("line test.db")).unwrap());
+        assert!(!is_ignored_by_git(&root.join("line-test.db")).unwrap());
+    }
+}
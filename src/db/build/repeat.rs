@@ -0,0 +1,170 @@
+use crate::{db::layout, run, warn, PackageCrateMap, RangeSet, Test};
+use anyhow::{Context, Result};
+use lcov::Report;
+use std::{collections::BTreeMap, fmt::Write as _, fs::write, path::PathBuf};
+
+/// The lines a test covered in every run, and in any run.
+#[derive(Default)]
+struct Runs {
+    report: Option<Report>,
+    always: BTreeMap<PathBuf, RangeSet<u32>>,
+    ever: BTreeMap<PathBuf, RangeSet<u32>>,
+}
+
+/// Runs the tests in `package_crate_test_map` `repeat - 1` more times, after they have been run
+/// once. Each test's coverage file is replaced with the union of its runs' coverage, and a warning
+/// is emitted for each test whose coverage differed between runs.
+pub(super) fn run_repeatedly(
+    package_crate_test_map: &PackageCrateMap<Vec<Test>>,
+    repeat: u32,
+) -> Result<()> {
+    let mut test_runs = BTreeMap::<_, Runs>::new();
+
+    for i in 0..repeat {
+        if i != 0 {
+            run::run_tests(package_crate_test_map, true)?;
+        }
+        for (package, crate_test_map) in package_crate_test_map {
+            for (krate, tests) in crate_test_map {
+                for test in tests {
+                    let path_buf = layout::lcov_path(layout::root(), package, krate, test);
+                    // smoelius: A test that fails to build leaves no coverage file.
+                    if !path_buf.try_exists()? {
+                        continue;
+                    }
+                    let report = Report::from_file(&path_buf)
+                        .with_context(|| format!("failed to read {}", path_buf.display()))?;
+                    test_runs
+                        .entry((package, krate, test))
+                        .or_default()
+                        .add(report);
+                }
+            }
+        }
+    }
+
+    for ((package, krate, test), runs) in test_runs {
+        let unstable = runs.unstable();
+        if !unstable.is_empty() {
+            let mut msg = format!(
+                "coverage of package `{package}` crate `{krate}` test `{test}` is unstable across \
+                 {repeat} runs"
+            );
+            for (path, line_set) in unstable {
+                write!(msg, "\n    {}: {line_set}", path.display()).unwrap();
+            }
+            warn(&msg)?;
+        }
+        if let Some(report) = runs.report {
+            let path_buf = layout::lcov_path(layout::root(), package, krate, test);
+            let mut lcov = String::new();
+            for record in report.into_records() {
+                writeln!(lcov, "{record}").unwrap();
+            }
+            write(&path_buf, lcov)
+                .with_context(|| format!("failed to write {}", path_buf.display()))?;
+        }
+    }
+
+    Ok(())
+}
+
+impl Runs {
+    fn add(&mut self, report: Report) {
+        let covered = covered_lines(&report);
+        if self.report.is_none() {
+            self.always.clone_from(&covered);
+        } else {
+            for (path, line_set) in &mut self.always {
+                *line_set = covered
+                    .get(path)
+                    .map(|covered| line_set.intersection(covered))
+                    .unwrap_or_default();
+            }
+        }
+        for (path, line_set) in covered {
+            self.ever.entry(path).or_default().union(line_set);
+        }
+        match &mut self.report {
+            Some(union) => union.merge_lossy(report),
+            None => self.report = Some(report),
+        }
+    }
+
+    /// Returns the lines covered in some runs but not others.
+    fn unstable(&self) -> BTreeMap<&PathBuf, RangeSet<u32>> {
+        self.ever
+            .iter()
+            .filter_map(|(path, ever)| {
+                let line_set = match self.always.get(path) {
+                    Some(always) => ever.difference(always),
+                    None => ever.clone(),
+                };
+                if line_set.is_empty() {
+                    None
+                } else {
+                    Some((path, line_set))
+                }
+            })
+            .collect()
+    }
+}
+
+fn covered_lines(report: &Report) -> BTreeMap<PathBuf, RangeSet<u32>> {
+    let mut covered = BTreeMap::<PathBuf, RangeSet<u32>>::new();
+    for (key, value) in &report.sections {
+        let line_set = value
+            .lines
+            .iter()
+            .filter(|(_, value)| value.count != 0)
+            .map(|(key, _)| key.line)
+            .collect::<RangeSet<_>>();
+        covered
+            .entry(key.source_file.clone())
+            .or_default()
+            .union(line_set);
+    }
+    covered
+}
+
+#[cfg(test)]
+mod test {
+    use super::Runs;
+    use lcov::{Reader, Report};
+    use std::{fmt::Write, path::Path};
+
+    fn report(lines: &[u32]) -> Report {
+        let mut lcov = String::from("SF:src/lib.rs\n");
+        for line in lines {
+            writeln!(lcov, "DA:{line},1").unwrap();
+        }
+        lcov.push_str("DA:9,0\nend_of_record\n");
+        Report::from_reader(Reader::new(lcov.as_bytes())).unwrap()
+    }
+
+    #[test]
+    fn stable_coverage() {
+        let mut runs = Runs::default();
+        runs.add(report(&[1, 2]));
+        runs.add(report(&[1, 2]));
+        assert!(runs.unstable().is_empty());
+    }
+
+    // smoelius: A flaky test, e.g., one that covers line 3 only when a race is won, is flagged with
+    // the lines covered in some runs but not others.
+    #[test]
+    fn unstable_coverage() {
+        let mut runs = Runs::default();
+        runs.add(report(&[1, 2]));
+        runs.add(report(&[1, 3]));
+        runs.add(report(&[1, 2]));
+        let unstable = runs.unstable();
+        assert_eq!(
+            "2-3",
+            unstable
+                .get(&Path::new("src/lib.rs").to_path_buf())
+                .unwrap()
+                .to_string()
+        );
+    }
+}
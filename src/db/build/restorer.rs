@@ -1,14 +1,14 @@
 use anyhow::{anyhow, Result};
 use std::{
     ffi::OsString,
-    fs::{remove_dir_all, rename},
+    fs::rename,
     path::{Path, PathBuf},
 };
 use tempfile::TempDir;
 
 pub struct Restorer {
     canonical_path: PathBuf,
-    tempdir: TempDir,
+    tempdir: Option<TempDir>,
     filename: OsString,
     disabled: bool,
 }
@@ -18,11 +18,20 @@ impl Restorer {
     where
         P: AsRef<Path>,
     {
-        let (canonical_path, tempdir, filename) = sibling_tempdir(path)?;
+        Self::new_in(path, None)
+    }
+
+    /// Like `new`, but saves the copy in a temporary directory within `dir` rather than beside
+    /// `path`. `dir` must be on the same filesystem as `path`.
+    pub fn new_in<P>(path: P, dir: Option<&Path>) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let (canonical_path, tempdir, filename) = tempdir_in(path, dir)?;
         rename(&canonical_path, tempdir.path().join(&filename))?;
         Ok(Self {
             canonical_path,
-            tempdir,
+            tempdir: Some(tempdir),
             filename,
             disabled: false,
         })
@@ -31,31 +40,127 @@ impl Restorer {
     pub fn disable(&mut self) {
         self.disabled = true;
     }
+
+    /// Moves the saved copy back to the original path. On failure, the temporary directory is kept,
+    /// and the returned message says where the saved copy can be recovered from.
+    fn restore(&mut self) -> Result<(), String> {
+        let Some(tempdir) = self.tempdir.take() else {
+            return Ok(());
+        };
+        let saved = tempdir.path().join(&self.filename);
+        // smoelius: The partial copy is moved into the temporary directory rather than removed, so
+        // that it is deleted (along with the temporary directory) only once the saved copy is back
+        // in place.
+        let partial = tempdir.path().join(PARTIAL);
+        let result = (|| {
+            if self.canonical_path.try_exists()? {
+                rename(&self.canonical_path, &partial)?;
+            }
+            rename(&saved, &self.canonical_path)
+        })();
+        if let Err(error) = result {
+            let _: PathBuf = tempdir.into_path();
+            return Err(format!(
+                "failed to restore {}: {error}; the saved copy is at {}",
+                self.canonical_path.display(),
+                saved.display()
+            ));
+        }
+        Ok(())
+    }
 }
 
+const PARTIAL: &str = "partial";
+
 impl Drop for Restorer {
     fn drop(&mut self) {
         if self.disabled {
             return;
         }
-        remove_dir_all(&self.canonical_path).unwrap_or_default();
-        rename(
-            self.tempdir.path().join(&self.filename),
-            &self.canonical_path,
-        )
-        .unwrap_or_default();
+        // smoelius: `drop` can be called while unwinding from a panic, so the failure is printed
+        // rather than returned or raised.
+        if let Err(msg) = self.restore() {
+            eprintln!("Warning: {msg}");
+        }
     }
 }
 
-fn sibling_tempdir(path: impl AsRef<Path>) -> Result<(PathBuf, TempDir, OsString)> {
+fn tempdir_in(path: impl AsRef<Path>, dir: Option<&Path>) -> Result<(PathBuf, TempDir, OsString)> {
     let canonical_path = path.as_ref().canonicalize()?;
     let parent = canonical_path
         .parent()
         .expect("`parent` should not fail for a canonical path");
-    let tempdir = TempDir::new_in(parent)?;
+    let tempdir = TempDir::new_in(dir.unwrap_or(parent))?;
     let filename = canonical_path
         .file_name()
         .map(ToOwned::to_owned)
         .ok_or_else(|| anyhow!("path has no filename: {}", canonical_path.display()))?;
     Ok((canonical_path, tempdir, filename))
 }
+
+#[cfg(test)]
+mod test {
+    use super::{Restorer, PARTIAL};
+    use std::fs::{create_dir, create_dir_all, read_to_string, write};
+    use tempfile::tempdir;
+
+    #[test]
+    fn restores_saved_copy() {
+        let tempdir = tempdir().unwrap();
+        let db = tempdir.path().join("line-test.db");
+        create_dir(&db).unwrap();
+        write(db.join("file"), "saved").unwrap();
+
+        let restorer = Restorer::new(&db).unwrap();
+        create_dir(&db).unwrap();
+        write(db.join("file"), "partial").unwrap();
+        drop(restorer);
+
+        assert_eq!("saved", read_to_string(db.join("file")).unwrap());
+        assert_eq!(1, tempdir.path().read_dir().unwrap().count());
+    }
+
+    #[test]
+    fn restores_copy_saved_elsewhere() {
+        let tempdir = tempdir().unwrap();
+        let package = tempdir.path().join("packages/my-package");
+        create_dir_all(&package).unwrap();
+        write(package.join("file"), "saved").unwrap();
+
+        let restorer = Restorer::new_in(&package, Some(tempdir.path())).unwrap();
+        assert_eq!(0, package.parent().unwrap().read_dir().unwrap().count());
+        drop(restorer);
+
+        assert_eq!("saved", read_to_string(package.join("file")).unwrap());
+        assert_eq!(1, tempdir.path().read_dir().unwrap().count());
+    }
+
+    #[test]
+    fn failed_restore_points_at_saved_copy() {
+        let tempdir = tempdir().unwrap();
+        let db = tempdir.path().join("line-test.db");
+        create_dir(&db).unwrap();
+        write(db.join("file"), "saved").unwrap();
+
+        let mut restorer = Restorer::new(&db).unwrap();
+        create_dir(&db).unwrap();
+        write(db.join("file"), "partial").unwrap();
+
+        // smoelius: Make moving the partial copy aside fail by putting a non-empty directory where
+        // it would go.
+        let restorer_tempdir = restorer.tempdir.as_ref().unwrap().path().to_path_buf();
+        create_dir_all(restorer_tempdir.join(PARTIAL).join("blocker")).unwrap();
+
+        let msg = restorer.restore().unwrap_err();
+        let saved = restorer_tempdir.join("line-test.db");
+        assert!(
+            msg.contains(&format!("the saved copy is at {}", saved.display())),
+            "{msg}"
+        );
+        drop(restorer);
+
+        // smoelius: Neither copy was removed.
+        assert_eq!("saved", read_to_string(saved.join("file")).unwrap());
+        assert_eq!("partial", read_to_string(db.join("file")).unwrap());
+    }
+}
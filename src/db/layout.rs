@@ -0,0 +1,189 @@
+//! The paths of the files and directories that make up a line-test.db. Every such path should be
+//! computed by a function in this module.
+
+use crate::{opts, Test};
+use anyhow::{anyhow, Result};
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+/// The name of the database's root directory.
+pub const NAME: &str = "line-test.db";
+
+/// The database's root directory, i.e., the one given by --db-path or `LINE_TEST_DB`, or
+/// line-test.db in the project directory (see `opts::project_dir`).
+pub fn root() -> &'static Path {
+    opts::get()
+        .db_path
+        .as_deref()
+        .unwrap_or_else(|| Path::new(NAME))
+}
+
+pub fn readme_path(root: &Path) -> PathBuf {
+    root.join("README.txt")
+}
+
+pub fn packages_dir(root: &Path) -> PathBuf {
+    root.join("packages")
+}
+
+pub fn crate_dir(root: &Path, package: &str, krate: &str) -> PathBuf {
+    packages_dir(root).join(package).join(krate)
+}
+
+pub fn lcov_path(root: &Path, package: &str, krate: &str, test: &Test) -> PathBuf {
+    crate_dir(root, package, krate).join(format!("{}.lcov", encode_test(test)))
+}
+
+// smoelius: A test's coverage file is named after the test's string representation, with `%` and
+// characters that are not allowed in file names on some platforms (e.g., `/`) replaced by `%XX`,
+// where `XX` is the character's hexadecimal ASCII code. `:` is not replaced, so that the names of
+// existing databases' coverage files (e.g., `tests::it_works.lcov`) are unchanged.
+fn encode_test(test: &Test) -> String {
+    let mut encoded = String::new();
+    for c in test.to_string().chars() {
+        if must_encode(c) {
+            write!(encoded, "%{:02X}", u32::from(c)).unwrap();
+        } else {
+            encoded.push(c);
+        }
+    }
+    encoded
+}
+
+fn must_encode(c: char) -> bool {
+    c.is_ascii_control() || matches!(c, '%' | '/' | '\\' | '<' | '>' | '"' | '|' | '?' | '*')
+}
+
+/// Returns the test whose coverage file has the stem `file_stem`, i.e., reverses the encoding
+/// used by [`lcov_path`].
+pub fn decode_test(file_stem: &str) -> Result<Test> {
+    let mut decoded = String::new();
+    let mut chars = file_stem.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            decoded.push(c);
+            continue;
+        }
+        let hex = chars.by_ref().take(2).collect::<String>();
+        let code = u8::from_str_radix(&hex, 16)
+            .ok()
+            .filter(|&code| hex.len() == 2 && must_encode(char::from(code)))
+            .ok_or_else(|| anyhow!("invalid escape `%{hex}` in coverage file name: {file_stem}"))?;
+        decoded.push(char::from(code));
+    }
+    decoded.parse()
+}
+
+pub fn build_time_path(root: &Path) -> PathBuf {
+    root.join("build-time.json")
+}
+
+pub fn digests_path(root: &Path) -> PathBuf {
+    root.join("digests.json")
+}
+
+pub fn hash_algo_path(root: &Path) -> PathBuf {
+    root.join("hash-algo.txt")
+}
+
+pub fn index_path(root: &Path) -> PathBuf {
+    root.join("index.json")
+}
+
+pub fn manifest_digests_path(root: &Path) -> PathBuf {
+    root.join("manifest-digests.json")
+}
+
+pub fn test_args_path(root: &Path) -> PathBuf {
+    root.join("test-args.json")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        build_time_path, crate_dir, decode_test, digests_path, encode_test, hash_algo_path,
+        index_path, lcov_path, manifest_digests_path, packages_dir, readme_path, test_args_path,
+    };
+    use crate::Test;
+    use proptest::{collection::vec, prelude::any, proptest, sample::select};
+    use std::path::Path;
+
+    #[test]
+    fn paths_are_beneath_root() {
+        let root = Path::new("elsewhere/other.db");
+        let test = "tests::it_works".parse().unwrap();
+        for path in [
+            readme_path(root),
+            packages_dir(root),
+            crate_dir(root, "package", "lib"),
+            lcov_path(root, "package", "lib", &test),
+            build_time_path(root),
+            digests_path(root),
+            hash_algo_path(root),
+            index_path(root),
+            manifest_digests_path(root),
+            test_args_path(root),
+        ] {
+            assert!(path.starts_with(root), "{}", path.display());
+        }
+        assert_eq!(
+            Path::new("elsewhere/other.db/packages/package/lib/tests::it_works.lcov"),
+            lcov_path(root, "package", "lib", &test)
+        );
+    }
+
+    #[test]
+    fn special_characters_are_encoded() {
+        let test = Test(vec![
+            String::from("tests"),
+            String::from("a/b%c"),
+            String::from("d:e"),
+        ]);
+        let encoded = encode_test(&test);
+        assert_eq!("tests::a%2Fb%25c::d%5C:e", encoded);
+        assert_eq!(test, decode_test(&encoded).unwrap());
+    }
+
+    #[test]
+    fn dotted_test_keeps_its_name() {
+        let test = "tests::foo.bar".parse().unwrap();
+        assert_eq!(
+            Path::new("line-test.db/packages/package/lib/tests::foo.bar.lcov"),
+            lcov_path(Path::new("line-test.db"), "package", "lib", &test)
+        );
+    }
+
+    #[test]
+    fn invalid_escapes_are_rejected() {
+        for file_stem in ["tests::%", "tests::%2", "tests::%zz", "tests::%41"] {
+            assert!(decode_test(file_stem).is_err(), "{file_stem}");
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn encoding_round_trips(
+            segments in vec(
+                vec(
+                    select(&['a', 'Z', '0', '_', '.', ' ', ':', '%', '/', '\\', '<', '>', '"', '|',
+                        '?', '*', '\0', '\n', '\u{7f}', 'é', '🦀'][..]),
+                    1..8,
+                ),
+                1..4,
+            ),
+            suffix in any::<String>(),
+        ) {
+            let mut segments = segments
+                .into_iter()
+                .map(|chars| chars.into_iter().collect::<String>())
+                .collect::<Vec<_>>();
+            segments.push(suffix);
+            let test = Test(segments);
+            let encoded = encode_test(&test);
+            assert!(!encoded.chars().any(|c| c != '%' && super::must_encode(c)));
+            assert_eq!(test, decode_test(&encoded).unwrap());
+        }
+    }
+}
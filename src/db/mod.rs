@@ -1,18 +1,179 @@
-use crate::{PackageCrateMap, PathCoverageMap, PathDigestMap, Test};
-use anyhow::Result;
-use std::collections::BTreeMap;
+use crate::{
+    util, HashAlgo, PackageCrateMap, PathBranchMap, PathCoverageMap, PathDigestMap,
+    PathFunctionMap, PathLineCountMap, PathLineMap, RangeSet, Test,
+};
+use anyhow::{ensure, Result};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env::current_dir,
+    path::{Path, PathBuf},
+};
 
 mod build;
+pub mod layout;
 mod read;
+mod verify;
+
+/// Maps each test to the paths that it covers.
+pub type Index = PackageCrateMap<BTreeMap<Test, BTreeSet<String>>>;
+
+/// Maps each test to the lines it covers, as `RangeSet`s rather than `HashSet`s.
+pub type RangeCoverageMap = PackageCrateMap<BTreeMap<Test, PathLineMap>>;
+
+/// Converts `coverage_map`'s line sets to `RangeSet`s. Dense coverage is represented more
+/// compactly, and the line sets can be compared with line specifications' directly.
+pub fn range_coverage_map(
+    coverage_map: &PackageCrateMap<BTreeMap<Test, PathCoverageMap>>,
+) -> RangeCoverageMap {
+    coverage_map
+        .iter()
+        .map(|(package, crate_map)| {
+            let crate_map = crate_map
+                .iter()
+                .map(|(krate, test_map)| {
+                    let test_map = test_map
+                        .iter()
+                        .map(|(test, path_coverage_map)| {
+                            let path_line_map = path_coverage_map
+                                .iter()
+                                .map(|(path, lines)| {
+                                    (path.clone(), lines.iter().copied().collect())
+                                })
+                                .collect();
+                            (test.clone(), path_line_map)
+                        })
+                        .collect();
+                    (krate.clone(), test_map)
+                })
+                .collect();
+            (package.clone(), crate_map)
+        })
+        .collect()
+}
 
 pub struct Db {
+    pub root: PathBuf,
+    pub source_root: PathBuf,
     pub package_crate_test_map: PackageCrateMap<Vec<Test>>,
     pub path_digest_map: PathDigestMap,
+    pub hash_algo: HashAlgo,
 }
 
 impl Db {
     pub fn coverage_map(&self) -> Result<PackageCrateMap<BTreeMap<Test, PathCoverageMap>>> {
-        read::read_coverage_map(&self.package_crate_test_map)
+        read::read_coverage_map(&self.root, &self.source_root, &self.package_crate_test_map)
+    }
+
+    /// Like [`Db::coverage_map`], but retains coverage only for the paths in `path_line_map`.
+    pub fn coverage_map_for_paths(
+        &self,
+        path_line_map: &PathLineMap,
+    ) -> Result<PackageCrateMap<BTreeMap<Test, PathCoverageMap>>> {
+        read::read_coverage_map_for_paths(
+            &self.root,
+            &self.source_root,
+            &self.package_crate_test_map,
+            path_line_map,
+        )
+    }
+
+    /// Returns the paths each test covers. The index is read if it exists; otherwise, the coverage
+    /// files are parsed.
+    pub fn index(&self) -> Result<Index> {
+        if let Some(index) = read::read_index(&self.root)? {
+            return Ok(index);
+        }
+        let coverage_map = self.coverage_map()?;
+        Ok(coverage_map
+            .into_iter()
+            .map(|(package, crate_map)| {
+                let crate_map = crate_map
+                    .into_iter()
+                    .map(|(krate, test_map)| {
+                        let test_map = test_map
+                            .into_iter()
+                            .map(|(test, path_map)| (test, path_map.into_keys().collect()))
+                            .collect();
+                        (krate, test_map)
+                    })
+                    .collect();
+                (package, crate_map)
+            })
+            .collect())
+    }
+
+    /// Returns the workspace's Cargo.lock and manifests that have changed since the database was
+    /// built. Changes to dependencies can change which lines tests execute.
+    pub fn changed_manifests(&self) -> Result<Vec<String>> {
+        let Some(manifest_digest_map) = read::read_manifest_digest_map(&self.root)? else {
+            return Ok(Vec::new());
+        };
+        let mut changed = Vec::new();
+        for (path, digest) in manifest_digest_map {
+            let source_path = self.source_root.join(&path);
+            if !source_path.try_exists()?
+                || util::hash_path_contents(&source_path, self.hash_algo)? != digest
+            {
+                changed.push(path);
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Returns the test binary arguments with which the database was built, or `None` if they were
+    /// not recorded.
+    pub fn test_args(&self) -> Result<Option<Vec<String>>> {
+        read::read_test_args(&self.root)
+    }
+
+    /// Returns every line with an lcov `DA` record in each test's coverage, with its execution
+    /// count. Unlike [`Db::coverage_map`], lines that were not executed are included.
+    pub fn line_count_map(&self) -> Result<PackageCrateMap<BTreeMap<Test, PathLineCountMap>>> {
+        read::read_line_count_map(
+            &self.root,
+            &self.source_root,
+            &self.package_crate_test_map,
+            &|_| true,
+        )
+    }
+
+    /// Returns, for each path in `path_line_map`, its instrumented lines, i.e., those with an lcov
+    /// `DA` record in some test's coverage, whether or not the line was executed.
+    pub fn instrumented_lines(&self, path_line_map: &PathLineMap) -> Result<PathLineMap> {
+        let line_count_map = read::read_line_count_map(
+            &self.root,
+            &self.source_root,
+            &self.package_crate_test_map,
+            &|path| path_line_map.contains_key(path),
+        )?;
+        let mut instrumented = PathLineMap::new();
+        for path_line_count_map in line_count_map
+            .values()
+            .flat_map(BTreeMap::values)
+            .flat_map(BTreeMap::values)
+        {
+            for (path, line_count_map) in path_line_count_map {
+                instrumented
+                    .entry(path.clone())
+                    .or_default()
+                    .union(line_count_map.keys().copied().collect());
+            }
+        }
+        Ok(instrumented)
+    }
+
+    pub fn branch_coverage_map(&self) -> Result<PackageCrateMap<BTreeMap<Test, PathBranchMap>>> {
+        read::read_branch_coverage_map(&self.root, &self.source_root, &self.package_crate_test_map)
+    }
+
+    pub fn function_coverage_map(
+        &self,
+    ) -> Result<PackageCrateMap<BTreeMap<Test, PathFunctionMap>>> {
+        read::read_function_coverage_map(
+            &self.root,
+            &self.source_root,
+            &self.package_crate_test_map,
+        )
     }
 }
 
@@ -20,10 +181,133 @@ pub fn build() -> Result<()> {
     build::build()
 }
 
-pub fn build_digests() -> Result<()> {
-    build::build_digests()
+pub fn build_digests(hash_algo: HashAlgo) -> Result<()> {
+    build::build_digests(hash_algo)
+}
+
+/// Rebuilds the digests of an existing database's covered files, e.g., after digests.json was
+/// deleted.
+pub fn rebuild_digests() -> Result<()> {
+    let root = layout::root();
+    ensure!(
+        layout::packages_dir(root).try_exists()?,
+        "{} does not exist; run with `--build` to build it",
+        root.display()
+    );
+    build::build_digests(read::read_hash_algo(root)?)
+}
+
+pub fn verify() -> Result<()> {
+    verify::verify(layout::root())
 }
 
 pub fn read() -> Result<Db> {
-    read::read()
+    read::read(layout::root(), &current_dir()?)
+}
+
+/// Reads the database rooted at `root`, whose coverage files refer to source files beneath
+/// `source_root`, e.g., a database built in another working tree.
+pub fn read_from(root: &Path, source_root: &Path) -> Result<Db> {
+    read::read(root, source_root)
+}
+
+/// Returns the `(package, crate, test)` triples of the tests that cover `line` of `path`, e.g., for
+/// an editor to show how many tests cover a line.
+pub fn tests_covering(db: &Db, path: &str, line: u32) -> Result<Vec<(String, String, Test)>> {
+    let path_line_map = PathLineMap::from([(path.to_owned(), RangeSet::from_iter([line]))]);
+    let coverage_map = db.coverage_map_for_paths(&path_line_map)?;
+    let mut tests = Vec::new();
+    for (package, crate_map) in coverage_map {
+        for (krate, test_map) in crate_map {
+            for (test, path_coverage_map) in test_map {
+                if path_coverage_map
+                    .get(path)
+                    .is_some_and(|coverage| coverage.contains(&line))
+                {
+                    tests.push((package.clone(), krate.clone(), test));
+                }
+            }
+        }
+    }
+    Ok(tests)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{read_from, tests_covering};
+    use crate::PathLineMap;
+    use std::fs::{create_dir_all, write};
+    use tempfile::tempdir;
+
+    #[test]
+    fn instrumented_lines() {
+        let tempdir = tempdir().unwrap();
+        let source_root = tempdir.path();
+        let root = source_root.join("line-test.db");
+        let dir = root.join("packages/package/lib");
+        create_dir_all(&dir).unwrap();
+        write(
+            dir.join("tests::a.lcov"),
+            concat!(
+                "SF:src/lib.rs\nDA:1,1\nDA:2,0\nDA:5,0\nend_of_record\n",
+                "SF:src/other.rs\nDA:1,1\nend_of_record\n",
+            ),
+        )
+        .unwrap();
+        write(
+            dir.join("tests::b.lcov"),
+            "SF:src/lib.rs\nDA:2,1\nDA:7,0\nend_of_record\n",
+        )
+        .unwrap();
+        write(root.join("digests.json"), "{}").unwrap();
+
+        let db = read_from(&root, source_root).unwrap();
+        let path_line_map = PathLineMap::from([(String::from("src/lib.rs"), (1..10).collect())]);
+        let instrumented = db.instrumented_lines(&path_line_map).unwrap();
+        assert_eq!(
+            vec!["src/lib.rs"],
+            instrumented.keys().map(String::as_str).collect::<Vec<_>>()
+        );
+        assert_eq!("1-2,5,7", instrumented["src/lib.rs"].to_string());
+    }
+
+    #[test]
+    fn tests_covering_line() {
+        let tempdir = tempdir().unwrap();
+        let source_root = tempdir.path();
+        let root = source_root.join("line-test.db");
+        for (krate, test, lines) in [
+            ("lib", "tests::a", [1, 2]),
+            ("lib", "tests::b", [2, 3]),
+            ("integration", "c", [3, 4]),
+        ] {
+            let dir = root.join("packages/package").join(krate);
+            create_dir_all(&dir).unwrap();
+            let lcov = format!(
+                "SF:src/lib.rs\nDA:{},1\nDA:{},1\nend_of_record\n",
+                lines[0], lines[1]
+            );
+            write(dir.join(format!("{test}.lcov")), lcov).unwrap();
+        }
+        write(root.join("digests.json"), "{}").unwrap();
+
+        let db = read_from(&root, source_root).unwrap();
+        let tests = tests_covering(&db, "src/lib.rs", 3).unwrap();
+        assert_eq!(
+            vec![
+                (
+                    String::from("package"),
+                    String::from("integration"),
+                    "c".parse().unwrap()
+                ),
+                (
+                    String::from("package"),
+                    String::from("lib"),
+                    "tests::b".parse().unwrap()
+                ),
+            ],
+            tests
+        );
+        assert!(tests_covering(&db, "src/main.rs", 3).unwrap().is_empty());
+    }
 }
@@ -1,31 +1,66 @@
-use super::{Db, PathDigestMap};
-use crate::{CrateMap, PackageCrateMap, PathCoverageMap, Test};
-use anyhow::{anyhow, bail, ensure, Result};
+use super::{layout, Db, Index, PathDigestMap};
+use crate::{
+    ignore, warn, CrateMap, HashAlgo, PackageCrateMap, PathBranchMap, PathCoverageMap,
+    PathFunctionMap, PathLineCountMap, PathLineMap, Test,
+};
+use anyhow::{anyhow, bail, Result};
+use clap::ValueEnum;
 use lcov::{Reader, Record};
 use std::{
-    collections::{BTreeMap, HashSet},
-    env::current_dir,
-    ffi::OsStr,
-    fs::{read_dir, read_to_string},
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fs::{read_dir, read_to_string, File},
     os::unix::ffi::OsStrExt,
     path::Path,
 };
 
-pub(super) fn read() -> Result<Db> {
-    let package_crate_test_map = read_package_crate_test_map()?;
-    let path_digest_map = read_path_digest_map()?;
+pub(super) fn read(root: &Path, source_root: &Path) -> Result<Db> {
+    warn_about_tests_without_lcov(root)?;
+
+    let package_crate_test_map = read_package_crate_test_map(root)?;
+    let path_digest_map = read_path_digest_map(root)?;
+    let hash_algo = read_hash_algo(root)?;
 
     Ok(Db {
+        root: root.to_path_buf(),
+        source_root: source_root.to_path_buf(),
         package_crate_test_map,
         path_digest_map,
+        hash_algo,
     })
 }
 
-pub(super) fn read_package_crate_test_map() -> Result<PackageCrateMap<Vec<Test>>> {
+// smoelius: A test whose coverage file is missing (e.g., because the build that would have written
+// it was interrupted) is effectively absent from the database. Such gaps are reported up front,
+// rather than leaving the user to wonder why the test is never selected.
+fn warn_about_tests_without_lcov(root: &Path) -> Result<()> {
+    let Some(index) = read_index(root)? else {
+        return Ok(());
+    };
+    let mut tests = Vec::new();
+    for (package, crate_index) in &index {
+        for (krate, test_index) in crate_index {
+            for test in test_index.keys() {
+                let path_buf = layout::lcov_path(root, package, krate, test);
+                if File::open(&path_buf).is_err() {
+                    tests.push(format!("package `{package}` crate `{krate}` test `{test}`"));
+                }
+            }
+        }
+    }
+    if !tests.is_empty() {
+        warn(&format!(
+            "the following tests' coverage files are missing or unreadable; consider running with \
+             `--build --missing-only`: {tests:#?}"
+        ))?;
+    }
+    Ok(())
+}
+
+pub(super) fn read_package_crate_test_map(root: &Path) -> Result<PackageCrateMap<Vec<Test>>> {
     let mut package_crate_test_map = PackageCrateMap::<Vec<Test>>::default();
-    let path = Path::new("line-test.db/packages");
+    let path = layout::packages_dir(root);
     if path.try_exists()? {
-        for result in read_dir(path)? {
+        for result in read_dir(&path)? {
             let entry = result?;
             let path = entry.path();
             let file_stem = path.file_stem_utf8(None)?;
@@ -54,13 +89,74 @@ fn read_crate_dir(path: &Path) -> Result<Vec<Test>> {
         let entry = result?;
         let path = entry.path();
         let file_stem = path.file_stem_utf8(Some("lcov"))?;
-        tests.push(file_stem.split("::").map(ToOwned::to_owned).collect());
+        tests.push(layout::decode_test(file_stem)?);
     }
     Ok(tests)
 }
 
-fn read_path_digest_map() -> Result<PathDigestMap> {
-    let json = read_to_string("line-test.db/digests.json")?;
+pub(super) fn read_path_digest_map(root: &Path) -> Result<PathDigestMap> {
+    let path = layout::digests_path(root);
+    // smoelius: Without the digests, changed files cannot be detected. But the coverage files
+    // suffice to rebuild them.
+    if !path.try_exists()? && layout::packages_dir(root).try_exists()? {
+        bail!(
+            "{} is missing; run with `--fix` to rebuild it from the existing coverage, or with \
+             `--build` to rebuild the database",
+            path.display()
+        );
+    }
+    read_digest_map(&path)
+}
+
+/// Reads the paths each test covers, or returns `None` if the database was built before the index
+/// was introduced.
+pub(super) fn read_index(root: &Path) -> Result<Option<Index>> {
+    let path = layout::index_path(root);
+    if !path.try_exists()? {
+        return Ok(None);
+    }
+    let json = read_to_string(&path)?;
+    let value = serde_json::from_str::<
+        BTreeMap<String, CrateMap<BTreeMap<String, BTreeSet<String>>>>,
+    >(&json)?;
+    let mut index = Index::default();
+    for (package, crate_map) in value {
+        let index = index.entry(package).or_default();
+        for (krate, test_map) in crate_map {
+            let index = index.entry(krate).or_default();
+            for (test, paths) in test_map {
+                index.insert(test.parse()?, paths);
+            }
+        }
+    }
+    Ok(Some(index))
+}
+
+/// Reads the digests of the workspace's Cargo.lock and manifests, or returns `None` if the database
+/// was built before they were recorded.
+pub(super) fn read_manifest_digest_map(root: &Path) -> Result<Option<PathDigestMap>> {
+    let path = layout::manifest_digests_path(root);
+    if !path.try_exists()? {
+        return Ok(None);
+    }
+    read_digest_map(&path).map(Some)
+}
+
+/// Reads the test binary arguments with which the database was built, or returns `None` if the
+/// database was built before they were recorded.
+pub(super) fn read_test_args(root: &Path) -> Result<Option<Vec<String>>> {
+    let path = layout::test_args_path(root);
+    if !path.try_exists()? {
+        return Ok(None);
+    }
+    let json = read_to_string(&path)?;
+    serde_json::from_str(&json)
+        .map(Some)
+        .map_err(|error| anyhow!("failed to parse {}: {error}", path.display()))
+}
+
+pub(super) fn read_digest_map(path: &Path) -> Result<PathDigestMap> {
+    let json = read_to_string(path)?;
     let path_hex_map = serde_json::from_str::<BTreeMap<String, String>>(&json)?;
     let mut path_digest_map = BTreeMap::new();
     for (path, hex) in path_hex_map {
@@ -72,78 +168,366 @@ fn read_path_digest_map() -> Result<PathDigestMap> {
     Ok(path_digest_map)
 }
 
+// smoelius: Databases built before hash-algo.txt was introduced used SHA-256.
+pub(super) fn read_hash_algo(root: &Path) -> Result<HashAlgo> {
+    let path = layout::hash_algo_path(root);
+    if !path.try_exists()? {
+        return Ok(HashAlgo::Sha256);
+    }
+    let contents = read_to_string(&path)?;
+    HashAlgo::from_str(contents.trim_end(), false)
+        .map_err(|error| anyhow!("failed to parse {}: {error}", path.display()))
+}
+
 pub(super) fn read_coverage_map(
+    root: &Path,
+    source_root: &Path,
+    package_crate_test_map: &PackageCrateMap<Vec<Test>>,
+) -> Result<PackageCrateMap<BTreeMap<Test, PathCoverageMap>>> {
+    read_test_map(
+        root,
+        source_root,
+        package_crate_test_map,
+        &|_| true,
+        ingest_line_data,
+    )
+}
+
+/// Like [`read_coverage_map`], but retains coverage only for the lines in `path_line_map`. Records
+/// for other paths are discarded as they are parsed.
+pub(super) fn read_coverage_map_for_paths(
+    root: &Path,
+    source_root: &Path,
     package_crate_test_map: &PackageCrateMap<Vec<Test>>,
+    path_line_map: &PathLineMap,
 ) -> Result<PackageCrateMap<BTreeMap<Test, PathCoverageMap>>> {
-    let mut coverage_map = PackageCrateMap::<BTreeMap<Test, PathCoverageMap>>::default();
+    let mut coverage_map = read_test_map(
+        root,
+        source_root,
+        package_crate_test_map,
+        &|path| path_line_map.contains_key(path),
+        ingest_line_data,
+    )?;
+    for path_coverage_map in coverage_map
+        .values_mut()
+        .flat_map(BTreeMap::values_mut)
+        .flat_map(BTreeMap::values_mut)
+    {
+        for (path, coverage) in path_coverage_map {
+            let line_set = &path_line_map[path];
+            coverage.retain(|&line| line_set.contains(line));
+        }
+    }
+    Ok(coverage_map)
+}
+
+pub(super) fn read_line_count_map(
+    root: &Path,
+    source_root: &Path,
+    package_crate_test_map: &PackageCrateMap<Vec<Test>>,
+    retain: &dyn Fn(&str) -> bool,
+) -> Result<PackageCrateMap<BTreeMap<Test, PathLineCountMap>>> {
+    read_test_map(
+        root,
+        source_root,
+        package_crate_test_map,
+        retain,
+        ingest_line_count,
+    )
+}
+
+pub(super) fn read_branch_coverage_map(
+    root: &Path,
+    source_root: &Path,
+    package_crate_test_map: &PackageCrateMap<Vec<Test>>,
+) -> Result<PackageCrateMap<BTreeMap<Test, PathBranchMap>>> {
+    read_test_map(
+        root,
+        source_root,
+        package_crate_test_map,
+        &|_| true,
+        ingest_branch_data,
+    )
+}
+
+pub(super) fn read_function_coverage_map(
+    root: &Path,
+    source_root: &Path,
+    package_crate_test_map: &PackageCrateMap<Vec<Test>>,
+) -> Result<PackageCrateMap<BTreeMap<Test, PathFunctionMap>>> {
+    read_test_map(
+        root,
+        source_root,
+        package_crate_test_map,
+        &|_| true,
+        ingest_function_data,
+    )
+}
+
+fn read_test_map<T: Default>(
+    root: &Path,
+    source_root: &Path,
+    package_crate_test_map: &PackageCrateMap<Vec<Test>>,
+    retain: &dyn Fn(&str) -> bool,
+    ingest: fn(&mut T, &Record),
+) -> Result<PackageCrateMap<BTreeMap<Test, BTreeMap<String, T>>>> {
+    let mut test_map = PackageCrateMap::<BTreeMap<Test, BTreeMap<String, T>>>::default();
     for (package, crate_test_map) in package_crate_test_map {
-        let coverage_map = coverage_map.entry(package.clone()).or_default();
+        let test_map = test_map.entry(package.clone()).or_default();
         for (krate, tests) in crate_test_map {
-            let coverage_map = coverage_map.entry(krate.clone()).or_default();
+            let test_map = test_map.entry(krate.clone()).or_default();
             for test in tests {
-                let path_buf = Path::new("line-test.db/packages")
-                    .join(package)
-                    .join(krate)
-                    .join(test.to_string())
-                    .with_extension("lcov");
-                let path_coverage_map = read_lcov(&path_buf)?;
-                coverage_map.insert(test.clone(), path_coverage_map);
+                let path_buf = layout::lcov_path(root, package, krate, test);
+                let path_map = read_lcov(&path_buf, source_root, retain, ingest)?;
+                test_map.insert(test.clone(), path_map);
             }
         }
     }
-    Ok(coverage_map)
+    Ok(test_map)
 }
 
-fn read_lcov(path: &Path) -> Result<PathCoverageMap> {
-    let current_dir = current_dir()?;
-    let mut path_coverage_map = PathCoverageMap::default();
+pub(super) fn read_lcov<T: Default>(
+    path: &Path,
+    source_root: &Path,
+    retain: &dyn Fn(&str) -> bool,
+    ingest: fn(&mut T, &Record),
+) -> Result<BTreeMap<String, T>> {
+    let mut path_map = BTreeMap::default();
     let mut source_file = None;
-    let mut coverage = HashSet::new();
+    let mut retained = false;
+    let mut value = T::default();
     for result in Reader::open_file(path)? {
         match result? {
             Record::SourceFile { path } => {
                 if let Some(source_file) = source_file {
                     bail!("source file already given: {source_file}");
                 }
-                let path = path.strip_prefix(&current_dir)?;
+                // smoelius: Coverage files written by `--build` record paths relative to the
+                // source root, which makes the database relocatable. Older coverage files record
+                // absolute paths. A source file outside of the source root keeps its absolute path.
+                let path = path.strip_prefix(source_root).unwrap_or(&path);
                 let path_utf8 = std::str::from_utf8(path.as_os_str().as_bytes())?;
+                retained = retain(path_utf8) && !ignore::is_ignored(path_utf8)?;
                 source_file = Some(path_utf8.to_owned());
             }
-            Record::LineData {
-                line,
-                count,
-                checksum: _,
-            } if count != 0 => {
-                coverage.insert(line);
-            }
             Record::EndOfRecord => {
                 let Some(key) = source_file else {
                     bail!("source file not given");
                 };
-                path_coverage_map.insert(key, coverage);
+                if retained {
+                    path_map.insert(key, value);
+                }
                 source_file = None;
-                coverage = HashSet::new();
+                value = T::default();
+            }
+            record => {
+                if retained {
+                    ingest(&mut value, &record);
+                }
             }
-            _ => {}
         }
     }
-    Ok(path_coverage_map)
+    Ok(path_map)
+}
+
+fn ingest_line_data(coverage: &mut HashSet<u32>, record: &Record) {
+    if let Record::LineData {
+        line,
+        count,
+        checksum: _,
+    } = *record
+    {
+        if count != 0 {
+            coverage.insert(line);
+        }
+    }
+}
+
+// smoelius: Unlike `ingest_line_data`, lines with zero counts are kept, since they are what
+// distinguish an instrumented but unexecuted line from a line that is not instrumented at all.
+fn ingest_line_count(counts: &mut BTreeMap<u32, u64>, record: &Record) {
+    if let Record::LineData {
+        line,
+        count,
+        checksum: _,
+    } = *record
+    {
+        *counts.entry(line).or_default() += count;
+    }
+}
+
+fn ingest_branch_data(coverage: &mut HashSet<(u32, u32)>, record: &Record) {
+    if let Record::BranchData {
+        line,
+        block: _,
+        branch,
+        taken: Some(taken),
+    } = *record
+    {
+        if taken != 0 {
+            coverage.insert((line, branch));
+        }
+    }
+}
+
+fn ingest_function_data(functions: &mut BTreeSet<String>, record: &Record) {
+    if let Record::FunctionData { name, count } = record {
+        if *count != 0 {
+            functions.insert(format!("{:#}", rustc_demangle::demangle(name)));
+        }
+    }
 }
 
 trait FileStemUtf8 {
     fn file_stem_utf8(&self, expected_extension: Option<&str>) -> Result<&str>;
 }
 
+// smoelius: `Path::extension` considers only the text after the last dot. So the expected extension
+// is matched against the end of the file name explicitly. This allows compound extensions like
+// `lcov.gz`, and test names containing dots like `foo.bar` in `foo.bar.lcov`. If no extension is
+// expected, the whole file name is the stem.
 impl FileStemUtf8 for Path {
     fn file_stem_utf8(&self, expected_extension: Option<&str>) -> Result<&str> {
-        let extension = self.extension();
-        ensure!(
-            expected_extension.map(OsStr::new) == extension,
-            "unexpected file extension: {extension:?}"
-        );
-        let Some(file_stem_os) = self.file_stem() else {
-            bail!("path has no file stem: {}", self.display());
+        let Some(file_name_os) = self.file_name() else {
+            bail!("path has no file name: {}", self.display());
+        };
+        let file_name = std::str::from_utf8(file_name_os.as_bytes())?;
+        let Some(expected_extension) = expected_extension else {
+            return Ok(file_name);
         };
-        std::str::from_utf8(file_stem_os.as_bytes()).map_err(Into::into)
+        let file_stem = file_name
+            .strip_suffix(expected_extension)
+            .and_then(|prefix| prefix.strip_suffix('.'))
+            .filter(|file_stem| !file_stem.is_empty());
+        file_stem.ok_or_else(|| {
+            anyhow!(
+                "expected file extension `{expected_extension}`: {}",
+                self.display()
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        read_coverage_map, read_coverage_map_for_paths, read_package_crate_test_map, FileStemUtf8,
+    };
+    use crate::{PackageCrateMap, PathCoverageMap, PathLineMap, RangeSet, Test};
+    use std::{
+        collections::{BTreeMap, HashSet},
+        fmt::Write,
+        fs::{create_dir_all, write},
+        path::Path,
+    };
+    use tempfile::tempdir;
+
+    const LCOV: &str = "\
+SF:$ROOT/src/lib.rs
+DA:1,1
+end_of_record
+SF:$ROOT/src/main.rs
+DA:1,1
+end_of_record
+";
+
+    #[test]
+    fn unspecified_lines_are_not_retained() {
+        let tempdir = tempdir().unwrap();
+        let source_root = tempdir.path();
+        let root = source_root.join("line-test.db");
+        for (test, first) in [("tests::a", 1), ("tests::b", 501)] {
+            let mut lcov = String::new();
+            for path in ["src/lib.rs", "src/main.rs"] {
+                writeln!(lcov, "SF:{}/{path}", source_root.display()).unwrap();
+                for line in first..first + 1000 {
+                    writeln!(lcov, "DA:{line},1").unwrap();
+                }
+                writeln!(lcov, "end_of_record").unwrap();
+            }
+            let dir = root.join("packages/package/lib");
+            create_dir_all(&dir).unwrap();
+            write(dir.join(format!("{test}.lcov")), lcov).unwrap();
+        }
+
+        let package_crate_test_map = read_package_crate_test_map(&root).unwrap();
+        let full = read_coverage_map(&root, source_root, &package_crate_test_map).unwrap();
+        let path_line_map = PathLineMap::from([(
+            String::from("src/lib.rs"),
+            RangeSet::from_iter([500, 501, 502]),
+        )]);
+        let pruned = read_coverage_map_for_paths(
+            &root,
+            source_root,
+            &package_crate_test_map,
+            &path_line_map,
+        )
+        .unwrap();
+
+        assert_eq!(4000, n_lines(&full));
+        // smoelius: tests::a covers lines 500-502, and tests::b covers lines 501-502.
+        assert_eq!(5, n_lines(&pruned));
+    }
+
+    fn n_lines(coverage_map: &PackageCrateMap<BTreeMap<Test, PathCoverageMap>>) -> usize {
+        coverage_map
+            .values()
+            .flat_map(BTreeMap::values)
+            .flat_map(BTreeMap::values)
+            .flat_map(BTreeMap::values)
+            .map(HashSet::len)
+            .sum()
+    }
+
+    #[test]
+    fn unspecified_paths_are_not_retained() {
+        let tempdir = tempdir().unwrap();
+        let source_root = tempdir.path();
+        let root = source_root.join("line-test.db");
+        let dir = root.join("packages/package/lib");
+        create_dir_all(&dir).unwrap();
+        let lcov = LCOV.replace("$ROOT", &source_root.to_string_lossy());
+        write(dir.join("tests::a.lcov"), lcov).unwrap();
+
+        let package_crate_test_map = read_package_crate_test_map(&root).unwrap();
+        let path_line_map =
+            PathLineMap::from([(String::from("src/lib.rs"), RangeSet::from_iter([1]))]);
+        let coverage_map = read_coverage_map_for_paths(
+            &root,
+            source_root,
+            &package_crate_test_map,
+            &path_line_map,
+        )
+        .unwrap();
+
+        let path_coverage_map = coverage_map
+            .values()
+            .flat_map(|crate_coverage_map| crate_coverage_map.values())
+            .flat_map(|test_coverage_map| test_coverage_map.values())
+            .next()
+            .unwrap();
+        assert_eq!(
+            vec!["src/lib.rs"],
+            path_coverage_map.keys().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn file_stem_with_dots() {
+        let path = Path::new("foo.bar.lcov");
+        assert_eq!("foo.bar", path.file_stem_utf8(Some("lcov")).unwrap());
+        assert!(path.file_stem_utf8(Some("gz")).is_err());
+        assert_eq!("foo.bar.lcov", path.file_stem_utf8(None).unwrap());
+    }
+
+    #[test]
+    fn file_stem_with_compound_extension() {
+        let path = Path::new("foo.lcov.gz");
+        assert_eq!("foo", path.file_stem_utf8(Some("lcov.gz")).unwrap());
+        assert!(path.file_stem_utf8(Some("lcov")).is_err());
+        assert!(Path::new("foolcov.gz")
+            .file_stem_utf8(Some("lcov.gz"))
+            .is_err());
+        assert!(Path::new(".lcov").file_stem_utf8(Some("lcov")).is_err());
     }
 }
@@ -0,0 +1,91 @@
+use super::{build, layout, read};
+use anyhow::{bail, Result};
+use std::{env::current_dir, path::Path};
+
+/// Checks the integrity of the database rooted at `root`, reporting every problem found. Returns an
+/// error if there is at least one.
+pub(super) fn verify(root: &Path) -> Result<()> {
+    let source_root = current_dir()?;
+    let mut problems = Vec::new();
+
+    let package_crate_test_map = read::read_package_crate_test_map(root)?;
+
+    for (package, crate_test_map) in &package_crate_test_map {
+        for (krate, tests) in crate_test_map {
+            for test in tests {
+                let path_buf = layout::lcov_path(root, package, krate, test);
+                match read::read_lcov(&path_buf, &source_root, &|_| true, |(): &mut (), _| {}) {
+                    Ok(path_map) => {
+                        for path in path_map.keys() {
+                            if !Path::new(path).try_exists()? {
+                                problems.push(format!(
+                                    "{} refers to nonexistent source file {path}",
+                                    path_buf.display()
+                                ));
+                            }
+                        }
+                    }
+                    Err(error) => {
+                        problems.push(format!("failed to read {}: {error}", path_buf.display()));
+                    }
+                }
+            }
+        }
+    }
+
+    match read::read_path_digest_map(root) {
+        Ok(path_digest_map) => {
+            for path in path_digest_map.keys() {
+                if !Path::new(path).try_exists()? {
+                    problems.push(format!("digest for nonexistent source file {path}"));
+                }
+            }
+        }
+        Err(error) => {
+            problems.push(format!(
+                "failed to read {}: {error}",
+                layout::digests_path(root).display()
+            ));
+        }
+    }
+
+    let current_package_crate_test_map = build::package_crate_test_map(None)?;
+    for (package, crate_test_map) in &package_crate_test_map {
+        for (krate, tests) in crate_test_map {
+            let current_tests = current_package_crate_test_map
+                .get(package)
+                .and_then(|crate_test_map| crate_test_map.get(krate));
+            for test in tests {
+                if !current_tests.is_some_and(|tests| tests.contains(test)) {
+                    problems.push(format!(
+                        "coverage for test that no longer exists: {package} {krate} {test}"
+                    ));
+                }
+            }
+        }
+    }
+    for (package, crate_test_map) in &current_package_crate_test_map {
+        for (krate, tests) in crate_test_map {
+            let db_tests = package_crate_test_map
+                .get(package)
+                .and_then(|crate_test_map| crate_test_map.get(krate));
+            for test in tests {
+                if !db_tests.is_some_and(|tests| tests.contains(test)) {
+                    problems.push(format!("no coverage for test: {package} {krate} {test}"));
+                }
+            }
+        }
+    }
+
+    for problem in &problems {
+        eprintln!("{problem}");
+    }
+
+    if !problems.is_empty() {
+        bail!("found {} problem(s) in {}", problems.len(), root.display());
+    }
+
+    eprintln!("no problems found in {}", root.display());
+
+    Ok(())
+}
@@ -0,0 +1,259 @@
+use crate::{db, diff_from_base, opts, output, warn, Db, PathCoverageMap, PathLineMap};
+use anyhow::{anyhow, ensure, Result};
+use assert_cmd::output::OutputError;
+use sha2::{Digest, Sha256};
+use std::{
+    env::{current_exe, temp_dir},
+    ffi::OsStr,
+    fs::{create_dir_all, remove_dir_all, rename},
+    path::{Path, PathBuf},
+    process::Command,
+};
+use tempfile::TempDir;
+
+/// Reports the lines changed since `base` whose coverage was lost or gained.
+///
+/// The coverage for `base` is built in a temporary git worktree, which is removed afterward. The
+/// resulting line-test.db is cached beneath the system's temporary directory, keyed by `base`'s
+/// commit and the arguments the tests are built with, so that later comparisons can reuse it.
+pub(crate) fn coverage_delta(base: &str) -> Result<()> {
+    let root = db::layout::root();
+    ensure!(
+        root.try_exists()?,
+        "{} does not exist; run with `--build` to build it",
+        root.display()
+    );
+    let head = covered_lines(&db::read()?)?;
+
+    let sha = git([
+        "rev-parse",
+        "--verify",
+        "--end-of-options",
+        &format!("{base}^{{commit}}"),
+    ])?;
+    let sha = sha.trim_end();
+
+    let base_root = base_db(sha)?;
+    let base_coverage = covered_lines(&db::read_from(&base_root, &base_root)?)?;
+
+    let mut lost = PathLineMap::new();
+    let mut gained = PathLineMap::new();
+    for patched_file in diff_from_base(sha)? {
+        if patched_file.target_file == "/dev/null" {
+            continue;
+        }
+        let target_file = strip_diff_prefix(&patched_file.target_file, "b/")?;
+        let base_lines = if patched_file.source_file == "/dev/null" {
+            None
+        } else {
+            base_coverage.get(strip_diff_prefix(&patched_file.source_file, "a/")?)
+        };
+        let head_lines = head.get(target_file);
+        for hunk in patched_file.hunks() {
+            // smoelius: The diff has no context, so each hunk replaces its removed lines with its
+            // added lines. An added line lost coverage if it is not covered but some line it
+            // replaced was, and gained coverage if it is covered but no line it replaced was.
+            let base_covered = hunk.lines().iter().any(|line| {
+                line.is_removed()
+                    && line
+                        .source_line_no
+                        .and_then(|line_no| u32::try_from(line_no).ok())
+                        .is_some_and(|line_no| {
+                            base_lines.is_some_and(|lines| lines.contains(&line_no))
+                        })
+            });
+            for line in hunk.lines() {
+                let Some(target_line_no) = line.target_line_no.filter(|_| line.is_added()) else {
+                    continue;
+                };
+                let target_line_no = u32::try_from(target_line_no)?;
+                let head_covered = head_lines.is_some_and(|lines| lines.contains(&target_line_no));
+                #[allow(clippy::range_plus_one)]
+                let range = target_line_no..target_line_no + 1;
+                if base_covered && !head_covered {
+                    lost.entry(target_file.to_owned())
+                        .or_default()
+                        .insert_range(range);
+                } else if !base_covered && head_covered {
+                    gained
+                        .entry(target_file.to_owned())
+                        .or_default()
+                        .insert_range(range);
+                }
+            }
+        }
+    }
+
+    for (path, line_set) in &gained {
+        output::println(&format!("gained: {path}:{line_set}"))?;
+    }
+    for (path, line_set) in &lost {
+        output::println(&format!("lost: {path}:{line_set}"))?;
+    }
+
+    if !lost.is_empty() {
+        warn(&format!(
+            "lines in the following files lost coverage since {base}: {:#?}",
+            lost.keys().collect::<Vec<_>>()
+        ))?;
+    }
+
+    Ok(())
+}
+
+/// Returns the lines covered by any test in `db`.
+fn covered_lines(db: &Db) -> Result<PathCoverageMap> {
+    let mut covered = PathCoverageMap::new();
+    for crate_coverage_map in db.coverage_map()?.into_values() {
+        for test_coverage_map in crate_coverage_map.into_values() {
+            for path_coverage_map in test_coverage_map.into_values() {
+                for (path, coverage) in path_coverage_map {
+                    covered.entry(path).or_default().extend(coverage);
+                }
+            }
+        }
+    }
+    Ok(covered)
+}
+
+/// Returns the root of a line-test.db for the directory corresponding to the current one at `sha`,
+/// building the database if it is not cached.
+fn base_db(sha: &str) -> Result<PathBuf> {
+    let prefix = git(["rev-parse", "--show-prefix"])?;
+    let prefix = prefix.trim_end();
+    let args = build_args();
+
+    let cache_dir = temp_dir().join(format!(
+        "cargo-line-test-{sha}-{}",
+        cache_key(prefix, &args)
+    ));
+    let root = cache_dir.join(db::layout::NAME);
+
+    if db::layout::digests_path(&root).try_exists()? {
+        return Ok(root);
+    }
+
+    let worktree = Worktree::add(sha)?;
+    let dir = worktree.path().join(prefix);
+
+    eprintln!("building line-test.db for {sha}");
+    let mut command = Command::new(current_exe()?);
+    command.arg("line-test");
+    command.args(&args);
+    command.current_dir(&dir);
+    let status = command.status()?;
+    ensure!(status.success(), "command failed: {command:?}");
+
+    // smoelius: A previous, interrupted build could have left a partial database in the cache.
+    if root.try_exists()? {
+        remove_dir_all(&root)?;
+    }
+    create_dir_all(&cache_dir)?;
+    rename(dir.join(db::layout::NAME), &root)?;
+
+    Ok(root)
+}
+
+/// Returns the arguments with which the base's line-test.db is built. Options that affect which
+/// tests run, or how they are built, are passed along.
+fn build_args() -> Vec<String> {
+    // smoelius: Pass --db-path explicitly so that neither `LINE_TEST_DB` nor a configuration file
+    // can cause the base's database to be built elsewhere.
+    let mut args = vec![
+        String::from("--build"),
+        String::from("--db-path"),
+        String::from(db::layout::NAME),
+    ];
+    if opts::get().all_features {
+        args.push(String::from("--all-features"));
+    }
+    for features in &opts::get().features {
+        args.extend([String::from("--features"), features.clone()]);
+    }
+    if opts::get().include_ignored {
+        args.push(String::from("--include-ignored"));
+    }
+    if let Some(profile) = &opts::get().profile {
+        args.extend([String::from("--profile"), profile.clone()]);
+    }
+    if !opts::get().zzargs.is_empty() {
+        args.push(String::from("--"));
+        args.extend(opts::get().zzargs.iter().cloned());
+    }
+    args
+}
+
+/// Returns a key distinguishing databases built in different directories or with different
+/// arguments.
+fn cache_key(prefix: &str, args: &[String]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(prefix);
+    for arg in args {
+        hasher.update([0]);
+        hasher.update(arg);
+    }
+    hex::encode(&hasher.finalize()[..8])
+}
+
+/// A git worktree in a temporary directory. The worktree is removed when dropped.
+struct Worktree {
+    tempdir: TempDir,
+}
+
+impl Worktree {
+    fn add(sha: &str) -> Result<Self> {
+        let tempdir = tempfile::Builder::new()
+            .prefix("cargo-line-test-")
+            .tempdir()?;
+        git([
+            OsStr::new("worktree"),
+            OsStr::new("add"),
+            OsStr::new("--detach"),
+            tempdir.path().as_os_str(),
+            OsStr::new(sha),
+        ])?;
+        Ok(Self { tempdir })
+    }
+
+    fn path(&self) -> &Path {
+        self.tempdir.path()
+    }
+}
+
+impl Drop for Worktree {
+    fn drop(&mut self) {
+        // smoelius: `git worktree remove` also removes the worktree's entry in .git/worktrees.
+        if let Err(error) = git([
+            OsStr::new("worktree"),
+            OsStr::new("remove"),
+            OsStr::new("--force"),
+            self.path().as_os_str(),
+        ]) {
+            eprintln!(
+                "Warning: failed to remove worktree {}: {error}",
+                self.path().display()
+            );
+        }
+    }
+}
+
+fn strip_diff_prefix<'a>(path: &'a str, prefix: &str) -> Result<&'a str> {
+    path.strip_prefix(prefix)
+        .ok_or_else(|| anyhow!(r#"path does not begin with "{prefix}": {path}"#))
+}
+
+pub(crate) fn git<I, S>(args: I) -> Result<String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let mut command = Command::new("git");
+    command.args(args);
+    let output = command.output()?;
+    ensure!(
+        output.status.success(),
+        "command failed: {command:?}\n{}",
+        OutputError::new(output)
+    );
+    String::from_utf8(output.stdout).map_err(Into::into)
+}
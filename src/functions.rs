@@ -0,0 +1,64 @@
+use crate::{warn, PackageCrateMap, PathFunctionMap, Test};
+use anyhow::Result;
+use std::collections::BTreeMap;
+
+/// Returns the tests that execute functions in `path_function_map`, along with the functions that
+/// no test executes.
+pub(crate) fn tests_for_functions(
+    function_coverage_map: &PackageCrateMap<BTreeMap<Test, PathFunctionMap>>,
+    path_function_map: &PathFunctionMap,
+) -> (PackageCrateMap<Vec<Test>>, PathFunctionMap) {
+    let mut uncovered = path_function_map.clone();
+    let mut test_map = PackageCrateMap::<Vec<Test>>::default();
+    for (package, function_coverage_map) in function_coverage_map {
+        let test_map = test_map.entry(package.clone()).or_default();
+        for (krate, function_coverage_map) in function_coverage_map {
+            let test_map = test_map.entry(krate.clone()).or_default();
+            for (test, coverage) in function_coverage_map {
+                let mut added = false;
+                for (path, executed) in coverage {
+                    let Some(functions) = path_function_map.get(path) else {
+                        continue;
+                    };
+                    let uncovered = uncovered.get_mut(path).unwrap();
+                    for function in functions {
+                        if !executed.iter().any(|name| function_matches(name, function)) {
+                            continue;
+                        }
+                        uncovered.remove(function);
+                        if !added {
+                            test_map.push(test.clone());
+                            added = true;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    (test_map, uncovered)
+}
+
+/// Returns true if `name`, a demangled function path, ends with `function`.
+fn function_matches(name: &str, function: &str) -> bool {
+    name.strip_suffix(function)
+        .is_some_and(|prefix| prefix.is_empty() || prefix.ends_with("::"))
+}
+
+pub(crate) fn warn_about_uncovered_functions(path_function_map: &PathFunctionMap) -> Result<()> {
+    let uncovered = path_function_map
+        .iter()
+        .flat_map(|(path, functions)| {
+            functions
+                .iter()
+                .map(move |function| format!("{path}#{function}"))
+        })
+        .collect::<Vec<_>>();
+
+    if uncovered.is_empty() {
+        return Ok(());
+    }
+
+    warn(&format!(
+        "the following functions are not covered by any test: {uncovered:#?}"
+    ))
+}
@@ -0,0 +1,197 @@
+use crate::{cobertura::escape, PackageCrateMap, PathCoverageMap, PathLineMap, Test};
+use anyhow::{Context, Result};
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    fs::write,
+    ops::{Range, RangeInclusive},
+    path::Path,
+};
+
+const STYLE: &str = "\
+body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; margin-bottom: 2em; }
+th, td { border: 1px solid #ccc; padding: 0.25em 0.75em; text-align: left; vertical-align: top; }
+th { background: #eee; }
+.covered { background: #dfd; }
+.uncovered { background: #fdd; }
+code { font-family: monospace; }
+";
+
+/// Writes to `path` an HTML page listing each path in `path_line_map`, its specified lines, and
+/// the tests that cover them. Consecutive lines covered by the same tests are listed together.
+pub(crate) fn write_html(
+    path: &Path,
+    coverage_map: &PackageCrateMap<BTreeMap<Test, PathCoverageMap>>,
+    path_line_map: &PathLineMap,
+) -> Result<()> {
+    let mut html = String::new();
+    writeln!(html, "<!DOCTYPE html>")?;
+    writeln!(html, "<html>")?;
+    writeln!(html, "<head>")?;
+    writeln!(html, r#"<meta charset="utf-8">"#)?;
+    writeln!(html, "<title>cargo-line-test report</title>")?;
+    writeln!(html, "<style>\n{STYLE}</style>")?;
+    writeln!(html, "</head>")?;
+    writeln!(html, "<body>")?;
+    writeln!(html, "<h1>cargo-line-test report</h1>")?;
+    for (source_path, line_set) in path_line_map {
+        writeln!(html, "<h2><code>{}</code></h2>", escape(source_path))?;
+        writeln!(html, "<table>")?;
+        writeln!(html, "<tr><th>Lines</th><th>Covering tests</th></tr>")?;
+        for (lines, tests) in line_groups(coverage_map, source_path, line_set.clone()) {
+            let class = if tests.is_empty() {
+                "uncovered"
+            } else {
+                "covered"
+            };
+            let lines = if lines.start() == lines.end() {
+                lines.start().to_string()
+            } else {
+                format!("{}-{}", lines.start(), lines.end())
+            };
+            write!(html, r#"<tr class="{class}"><td>{lines}</td><td>"#)?;
+            if tests.is_empty() {
+                write!(html, "none")?;
+            }
+            for (i, test) in tests.iter().enumerate() {
+                if i != 0 {
+                    write!(html, "<br>")?;
+                }
+                write!(html, "<code>{}</code>", escape(test))?;
+            }
+            writeln!(html, "</td></tr>")?;
+        }
+        writeln!(html, "</table>")?;
+    }
+    writeln!(html, "</body>")?;
+    writeln!(html, "</html>")?;
+
+    write(path, html).with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// Returns the lines in `line_set` grouped into runs of consecutive lines covered by the same
+/// tests, along with those tests.
+fn line_groups(
+    coverage_map: &PackageCrateMap<BTreeMap<Test, PathCoverageMap>>,
+    path: &str,
+    line_set: impl IntoIterator<Item = Range<u32>>,
+) -> Vec<(RangeInclusive<u32>, Vec<String>)> {
+    let mut groups = Vec::<(RangeInclusive<u32>, Vec<String>)>::new();
+    for range in line_set {
+        for line in range {
+            let tests = covering_tests(coverage_map, path, line);
+            if let Some((lines, last_tests)) = groups.last_mut() {
+                if *lines.end() + 1 == line && *last_tests == tests {
+                    *lines = *lines.start()..=line;
+                    continue;
+                }
+            }
+            groups.push((line..=line, tests));
+        }
+    }
+    groups
+}
+
+fn covering_tests(
+    coverage_map: &PackageCrateMap<BTreeMap<Test, PathCoverageMap>>,
+    path: &str,
+    line: u32,
+) -> Vec<String> {
+    let mut tests = Vec::new();
+    for (package, coverage_map) in coverage_map {
+        for (krate, coverage_map) in coverage_map {
+            for (test, coverage_map) in coverage_map {
+                if coverage_map
+                    .get(path)
+                    .is_some_and(|coverage| coverage.contains(&line))
+                {
+                    tests.push(format!("{package} {krate} {test}"));
+                }
+            }
+        }
+    }
+    tests
+}
+
+#[cfg(test)]
+mod test {
+    use super::{line_groups, write_html};
+    use crate::{PackageCrateMap, PathCoverageMap, PathLineMap, Test};
+    use std::{collections::BTreeMap, fs::read_to_string};
+    use tempfile::tempdir;
+
+    /// Returns a coverage map in which each `(test, path, lines)` triple's test, in package
+    /// `package` and crate `lib`, covers the given lines of the given path.
+    fn coverage_map(
+        coverage: &[(&str, &str, &[u32])],
+    ) -> PackageCrateMap<BTreeMap<Test, PathCoverageMap>> {
+        let mut test_map = BTreeMap::<Test, PathCoverageMap>::new();
+        for &(test, path, lines) in coverage {
+            test_map
+                .entry(test.parse().unwrap())
+                .or_default()
+                .entry(path.to_owned())
+                .or_default()
+                .extend(lines);
+        }
+        PackageCrateMap::from([(
+            String::from("package"),
+            BTreeMap::from([(String::from("lib"), test_map)]),
+        )])
+    }
+
+    #[test]
+    fn groups_lines_covered_by_same_tests() {
+        let coverage_map = coverage_map(&[
+            ("tests::a", "src/lib.rs", &[1, 2, 3]),
+            ("tests::b", "src/lib.rs", &[3]),
+        ]);
+        let groups = line_groups(&coverage_map, "src/lib.rs", [1..5, 6..7]);
+        assert_eq!(
+            vec![
+                (1..=2, vec![String::from("package lib tests::a")]),
+                (
+                    3..=3,
+                    vec![
+                        String::from("package lib tests::a"),
+                        String::from("package lib tests::b")
+                    ]
+                ),
+                (4..=4, vec![]),
+                (6..=6, vec![]),
+            ],
+            groups
+        );
+    }
+
+    #[test]
+    fn html() {
+        let tempdir = tempdir().unwrap();
+        let path = tempdir.path().join("report.html");
+        let coverage_map = coverage_map(&[
+            ("tests::a", "src/lib.rs", &[1, 2]),
+            ("tests::a", "src/main.rs", &[1]),
+            ("tests::b", "src/lib.rs", &[2, 3]),
+        ]);
+        let path_line_map = PathLineMap::from([
+            (String::from("src/lib.rs"), (1..5).collect()),
+            (String::from("src/main.rs"), [1].into_iter().collect()),
+        ]);
+        write_html(&path, &coverage_map, &path_line_map).unwrap();
+
+        let html = read_to_string(&path).unwrap();
+        for expected in [
+            "<h2><code>src/lib.rs</code></h2>",
+            "<h2><code>src/main.rs</code></h2>",
+            r#"<tr class="covered"><td>1</td><td><code>package lib tests::a</code></td></tr>"#,
+            r#"<tr class="covered"><td>2</td><td><code>package lib tests::a</code><br><code>package lib tests::b</code></td></tr>"#,
+            r#"<tr class="covered"><td>3</td><td><code>package lib tests::b</code></td></tr>"#,
+            r#"<tr class="uncovered"><td>4</td><td>none</td></tr>"#,
+            "<style>",
+        ] {
+            assert!(html.contains(expected), "{expected}\n{html}");
+        }
+        assert!(!html.contains("<link"), "{html}");
+    }
+}
@@ -0,0 +1,85 @@
+use crate::opts;
+use anyhow::{Context, Result};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use once_cell::sync::OnceCell;
+use std::{env::current_dir, fs::read_to_string, path::Path};
+
+/// The name of the ignore file, which is read from the project directory (see
+/// [`opts::project_dir`]). Source files matching its patterns are neither stored in nor selected
+/// from the database.
+pub const IGNORE_FILE: &str = ".line-test-ignore";
+
+// smoelius: The patterns use gitignore syntax, and are relative to the project directory. Ignoring
+// a directory ignores everything beneath it.
+static IGNORE: OnceCell<Gitignore> = OnceCell::new();
+
+/// Returns whether `path`, relative to the current directory, matches the ignore file.
+pub fn is_ignored(path: &str) -> Result<bool> {
+    let current_dir = current_dir()?;
+    let gitignore =
+        IGNORE.get_or_try_init(|| read(&current_dir.join(opts::project_dir()).canonicalize()?))?;
+    Ok(matches(gitignore, &current_dir.join(path)))
+}
+
+fn read(root: &Path) -> Result<Gitignore> {
+    let path = root.join(IGNORE_FILE);
+    if !path.try_exists()? {
+        return Ok(Gitignore::empty());
+    }
+    let contents =
+        read_to_string(&path).with_context(|| format!("failed to read {}", path.display()))?;
+    parse(root, &contents).with_context(|| format!("failed to parse {}", path.display()))
+}
+
+fn parse(root: &Path, contents: &str) -> Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new(root);
+    for line in contents.lines() {
+        builder.add_line(None, line)?;
+    }
+    builder.build().map_err(Into::into)
+}
+
+fn matches(gitignore: &Gitignore, path: &Path) -> bool {
+    // smoelius: `matched_path_or_any_parents` panics on an absolute path outside of the root.
+    if path.has_root() && !path.starts_with(gitignore.path()) {
+        return false;
+    }
+    gitignore
+        .matched_path_or_any_parents(path, false)
+        .is_ignore()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{matches, parse};
+    use std::path::Path;
+
+    #[test]
+    fn patterns() {
+        let ignore = parse(
+            Path::new("/project"),
+            "# generated code\nsrc/generated/**\nvendor/\n*.pb.rs\n!src/keep.pb.rs\n",
+        )
+        .unwrap();
+        assert!(matches(&ignore, Path::new("src/generated/a.rs")));
+        assert!(matches(&ignore, Path::new("src/generated/nested/b.rs")));
+        assert!(!matches(&ignore, Path::new("src/generated.rs")));
+        assert!(matches(&ignore, Path::new("vendor/lib.rs")));
+        assert!(matches(&ignore, Path::new("crates/x/vendor/lib.rs")));
+        assert!(!matches(&ignore, Path::new("vendor")));
+        assert!(matches(&ignore, Path::new("src/proto/a.pb.rs")));
+        assert!(!matches(&ignore, Path::new("src/keep.pb.rs")));
+        assert!(!matches(&ignore, Path::new("src/lib.rs")));
+    }
+
+    #[test]
+    fn anchored_pattern_does_not_match_at_depth() {
+        let ignore = parse(Path::new("/project"), "/build.rs\nsrc/*.rs\n").unwrap();
+        assert!(matches(&ignore, Path::new("build.rs")));
+        assert!(!matches(&ignore, Path::new("crates/x/build.rs")));
+        assert!(matches(&ignore, Path::new("src/lib.rs")));
+        assert!(!matches(&ignore, Path::new("src/a/lib.rs")));
+        assert!(matches(&ignore, Path::new("/project/build.rs")));
+        assert!(!matches(&ignore, Path::new("/elsewhere/build.rs")));
+    }
+}
@@ -0,0 +1,75 @@
+use crate::{PackageCrateMap, PathCoverageMap, PathLineMap, Test};
+use std::collections::{BTreeMap, HashSet};
+
+/// Returns a small set of tests that together cover every line in `path_line_map` that any test
+/// covers.
+///
+/// The set is found greedily: at each step, the test covering the most not-yet-covered lines is
+/// selected. The result is not guaranteed to be minimal, but is typically close.
+pub(crate) fn minimize(
+    coverage_map: &PackageCrateMap<BTreeMap<Test, PathCoverageMap>>,
+    path_line_map: &PathLineMap,
+) -> PackageCrateMap<Vec<Test>> {
+    let mut candidates = Vec::new();
+    for (package, coverage_map) in coverage_map {
+        for (krate, coverage_map) in coverage_map {
+            for (test, coverage_map) in coverage_map {
+                let mut lines = HashSet::new();
+                for (path, coverage) in coverage_map {
+                    let Some(line_set) = path_line_map.get(path) else {
+                        continue;
+                    };
+                    lines.extend(
+                        coverage
+                            .iter()
+                            .filter(|&&line| line_set.contains(line))
+                            .map(|&line| (path.as_str(), line)),
+                    );
+                }
+                if !lines.is_empty() {
+                    candidates.push((package, krate, test, lines));
+                }
+            }
+        }
+    }
+
+    let mut remaining = candidates
+        .iter()
+        .flat_map(|(_, _, _, lines)| lines.iter().copied())
+        .collect::<HashSet<_>>();
+
+    let mut selected = vec![false; candidates.len()];
+    while !remaining.is_empty() {
+        // smoelius: `max_by_key` returns the last maximum element. Reverse the iterator so that
+        // ties are broken in favor of the test that comes first.
+        let Some((i, n)) = candidates
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(i, (_, _, _, lines))| (i, lines.intersection(&remaining).count()))
+            .max_by_key(|&(_, n)| n)
+        else {
+            break;
+        };
+        if n == 0 {
+            break;
+        }
+        selected[i] = true;
+        for line in &candidates[i].3 {
+            remaining.remove(line);
+        }
+    }
+
+    let mut test_map = PackageCrateMap::<Vec<Test>>::default();
+    for ((package, krate, test, _), selected) in candidates.into_iter().zip(selected) {
+        if selected {
+            test_map
+                .entry(package.clone())
+                .or_default()
+                .entry(krate.clone())
+                .or_default()
+                .push(test.clone());
+        }
+    }
+    test_map
+}
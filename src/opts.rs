@@ -1,19 +1,147 @@
-use crate::{CargoCommand, Opts, SubCommand};
+use crate::{db::layout, CargoCommand, HashAlgo, Opts, SubCommand};
+use anyhow::{ensure, Context, Result};
 use clap::Parser;
 use once_cell::sync::OnceCell;
+use serde::Deserialize;
+use std::{
+    env::var_os,
+    ffi::OsStr,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
 
 static OPTS: OnceCell<Opts> = OnceCell::new();
 
+static PROJECT_DIR: OnceCell<PathBuf> = OnceCell::new();
+
+/// The name of the configuration file, which is read from the project directory (see
+/// [`project_dir`]).
+const CONFIG: &str = ".line-test.toml";
+
+/// Defaults for options not passed on the command line.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    all_features: Option<bool>,
+    db_path: Option<PathBuf>,
+    db_readme: Option<String>,
+    deny_warnings: Option<bool>,
+    exclude_test: Option<Vec<String>>,
+    features: Option<Vec<String>>,
+    hash_algo: Option<HashAlgo>,
+    no_db_readme: Option<bool>,
+    no_progress: Option<bool>,
+    verbose: Option<bool>,
+}
+
+/// Parses the command line and applies the configuration file. Must be called before [`get`].
+pub(crate) fn init() -> Result<()> {
+    let opts = CargoCommand::parse();
+
+    let SubCommand::LineTest(mut opts) = opts.subcmd;
+
+    // smoelius: Only line-test.db and the configuration files are looked up in the manifest's
+    // directory. Paths passed to options, and the paths in line specifications and coverage files,
+    // remain relative to the current directory.
+    let project_dir = match &opts.manifest_path {
+        Some(manifest_path) => manifest_dir(manifest_path)?,
+        None => PathBuf::new(),
+    };
+
+    if opts.db_path.is_none() {
+        opts.db_path = var_os("LINE_TEST_DB").map(PathBuf::from);
+    }
+
+    let config = read_config(&project_dir.join(CONFIG))?;
+    apply_config(&mut opts, config, &project_dir);
+
+    if opts.db_path.is_none() && opts.manifest_path.is_some() {
+        opts.db_path = Some(project_dir.join(layout::NAME));
+    }
+
+    if opts.no_run {
+        opts.show_commands = true;
+    }
+
+    let _ = OPTS.set(opts);
+    let _ = PROJECT_DIR.set(project_dir);
+
+    Ok(())
+}
+
 pub(crate) fn get() -> &'static Opts {
-    OPTS.get_or_init(|| {
-        let opts = CargoCommand::parse();
+    OPTS.get().expect("`opts::init` should have been called")
+}
 
-        let SubCommand::LineTest(mut opts) = opts.subcmd;
+/// Returns the directory in which line-test.db and the configuration files are looked up: that of
+/// the manifest passed to --manifest-path, or else the current directory, represented by an empty
+/// path.
+// smoelius: Unit tests read databases without calling `init`, so the current directory is also
+// used if `init` has not been called.
+pub(crate) fn project_dir() -> &'static Path {
+    PROJECT_DIR
+        .get()
+        .map_or_else(|| Path::new(""), PathBuf::as_path)
+}
+
+fn manifest_dir(manifest_path: &Path) -> Result<PathBuf> {
+    ensure!(
+        manifest_path.file_name() == Some(OsStr::new("Cargo.toml")),
+        "--manifest-path must be a path to a Cargo.toml file: {}",
+        manifest_path.display()
+    );
+    ensure!(
+        manifest_path.try_exists()?,
+        "manifest path `{}` does not exist",
+        manifest_path.display()
+    );
+    Ok(manifest_path
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default())
+}
 
-        if opts.no_run {
-            opts.show_commands = true;
-        }
+fn read_config(path: &Path) -> Result<Config> {
+    if !path.try_exists()? {
+        return Ok(Config::default());
+    }
+    let contents = read_to_string(path)?;
+    toml::from_str(&contents).with_context(|| format!("failed to parse {}", path.display()))
+}
 
-        opts
-    })
+// smoelius: Options passed on the command line take precedence over those in the configuration
+// file, which take precedence over the built-in defaults. The one exception is the database's
+// location, for which the `LINE_TEST_DB` environment variable falls between the command line and
+// the configuration file. A flag cannot be unset on the command line, so a flag set in the
+// configuration file remains set. A relative `db_path` in the configuration file is relative to
+// `project_dir`, the directory containing the file.
+fn apply_config(opts: &mut Opts, config: Config, project_dir: &Path) {
+    let Config {
+        all_features,
+        db_path,
+        db_readme,
+        deny_warnings,
+        exclude_test,
+        features,
+        hash_algo,
+        no_db_readme,
+        no_progress,
+        verbose,
+    } = config;
+    opts.all_features |= all_features.unwrap_or_default();
+    if opts.db_path.is_none() {
+        opts.db_path = db_path.map(|db_path| project_dir.join(db_path));
+    }
+    opts.db_readme = db_readme;
+    opts.deny_warnings |= deny_warnings.unwrap_or_default();
+    if opts.exclude_test.is_empty() {
+        opts.exclude_test = exclude_test.unwrap_or_default();
+    }
+    if opts.features.is_empty() {
+        opts.features = features.unwrap_or_default();
+    }
+    opts.hash_algo = opts.hash_algo.or(hash_algo);
+    opts.no_db_readme |= no_db_readme.unwrap_or_default();
+    opts.no_progress |= no_progress.unwrap_or_default();
+    opts.verbose |= verbose.unwrap_or_default();
 }
@@ -0,0 +1,238 @@
+use crate::{opts, run, Format, PackageCrateMap, PathCoverageMap, PathLineMap, Test};
+use anyhow::{Context, Result};
+use once_cell::sync::OnceCell;
+use serde_json::{json, Map, Value};
+use std::{
+    collections::BTreeMap,
+    env::{var, var_os},
+    fs::{write, File},
+    io::{stdout, IsTerminal, Write},
+    ops::Range,
+    path::Path,
+    sync::Mutex,
+};
+
+static FILE: OnceCell<Mutex<File>> = OnceCell::new();
+
+/// Writes `line` to the file given by `--output`, or to standard output if no such file was given.
+pub(crate) fn println(line: &str) -> Result<()> {
+    let Some(path) = &opts::get().output else {
+        println!("{line}");
+        return Ok(());
+    };
+    let file = FILE.get_or_try_init(|| {
+        File::create(path)
+            .map(Mutex::new)
+            .with_context(|| format!("failed to create {}", path.display()))
+    })?;
+    let mut file = file.lock().unwrap();
+    writeln!(file, "{line}").with_context(|| format!("failed to write to {}", path.display()))
+}
+
+pub(crate) fn emit_selection(
+    test_map: &PackageCrateMap<Vec<Test>>,
+    uncovered: &PathLineMap,
+) -> Result<()> {
+    if opts::get().format != Format::Json {
+        return Ok(());
+    }
+    let value = json!({
+        "tests": tests_json(test_map),
+        "uncovered": path_line_map_json(uncovered),
+    });
+    println(&serde_json::to_string_pretty(&value)?)
+}
+
+fn tests_json(test_map: &PackageCrateMap<Vec<Test>>) -> Value {
+    let mut tests = Vec::new();
+    for (package, crate_test_map) in test_map {
+        for (krate, crate_tests) in crate_test_map {
+            for test in crate_tests {
+                tests.push(json!({
+                    "package": package,
+                    "crate": krate,
+                    "test": test.to_string(),
+                }));
+            }
+        }
+    }
+    Value::Array(tests)
+}
+
+/// Prints `test_map`'s tests grouped by package and crate, with each package's and crate's number
+/// of tests aligned after its name. Headers are colored if standard output is a terminal.
+pub(crate) fn print_test_groups(test_map: &PackageCrateMap<Vec<Test>>) -> Result<()> {
+    let (package_width, crate_width, _, _) = run::widths_and_count(test_map);
+    let color = use_color();
+    let paint = |code: &str, s: &str| {
+        if color {
+            format!("\x1b[{code}m{s}\x1b[0m")
+        } else {
+            s.to_owned()
+        }
+    };
+    for (package, crate_test_map) in test_map {
+        let n = crate_test_map.values().map(Vec::len).sum::<usize>();
+        if n == 0 {
+            continue;
+        }
+        println(&format!(
+            "{}  {}",
+            paint(BOLD, &format!("{package:package_width$}")),
+            count_tests(n)
+        ))?;
+        for (krate, tests) in crate_test_map {
+            if tests.is_empty() {
+                continue;
+            }
+            println(&format!(
+                "  {}  {}",
+                paint(CYAN, &format!("{krate:crate_width$}")),
+                count_tests(tests.len())
+            ))?;
+            for test in tests {
+                println(&format!("    {test}"))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+const BOLD: &str = "1";
+const CYAN: &str = "36";
+
+fn count_tests(n: usize) -> String {
+    format!("{n} test{}", if n == 1 { "" } else { "s" })
+}
+
+// smoelius: Colors are written only to a terminal, and never to the file given by `--output`. The
+// `NO_COLOR` convention (https://no-color.org) is honored.
+fn use_color() -> bool {
+    opts::get().output.is_none()
+        && stdout().is_terminal()
+        && var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+        && var("TERM").as_deref() != Ok("dumb")
+}
+
+/// Converts `path_line_map` to an object mapping each path to a list of `[start, end)` spans.
+pub(crate) fn path_line_map_json(path_line_map: &PathLineMap) -> Value {
+    let mut map = Map::new();
+    for (path, line_set) in path_line_map {
+        if line_set.is_empty() {
+            continue;
+        }
+        let spans = line_set
+            .clone()
+            .into_iter()
+            .map(|Range { start, end }| json!([start, end]))
+            .collect();
+        map.insert(path.clone(), Value::Array(spans));
+    }
+    Value::Object(map)
+}
+
+/// Writes `coverage_map` to `path` as JSON, mapping each package, crate, test, and source file to
+/// the sorted lines that the test covers.
+pub(crate) fn write_coverage_map(
+    path: &Path,
+    coverage_map: &PackageCrateMap<BTreeMap<Test, PathCoverageMap>>,
+) -> Result<()> {
+    let mut packages = Map::new();
+    for (package, crate_coverage_map) in coverage_map {
+        let mut crates = Map::new();
+        for (krate, test_coverage_map) in crate_coverage_map {
+            let mut tests = Map::new();
+            for (test, path_coverage_map) in test_coverage_map {
+                let mut paths = Map::new();
+                for (path, coverage) in path_coverage_map {
+                    let mut lines = coverage.iter().copied().collect::<Vec<_>>();
+                    lines.sort_unstable();
+                    paths.insert(path.clone(), json!(lines));
+                }
+                tests.insert(test.to_string(), Value::Object(paths));
+            }
+            crates.insert(krate.clone(), Value::Object(tests));
+        }
+        packages.insert(package.clone(), Value::Object(crates));
+    }
+    let json = serde_json::to_string_pretty(&packages)?;
+    write(path, json).with_context(|| format!("failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{path_line_map_json, tests_json, write_coverage_map};
+    use crate::{PackageCrateMap, PathCoverageMap, PathLineMap, Test};
+    use serde_json::{json, Value};
+    use std::{collections::BTreeMap, fs::read_to_string};
+    use tempfile::tempdir;
+
+    #[test]
+    fn path_line_map_spans() {
+        let path_line_map = PathLineMap::from([
+            (String::from("src/lib.rs"), [5, 7, 8].into_iter().collect()),
+            (String::from("src/main.rs"), [].into_iter().collect()),
+        ]);
+        assert_eq!(
+            json!({ "src/lib.rs": [[5, 6], [7, 9]] }),
+            path_line_map_json(&path_line_map)
+        );
+    }
+
+    #[test]
+    fn tests_are_listed_with_package_and_crate() {
+        let test_map = PackageCrateMap::<Vec<Test>>::from([(
+            String::from("package"),
+            BTreeMap::from([
+                (String::from("integration"), vec!["c".parse().unwrap()]),
+                (String::from("lib"), vec!["tests::a".parse().unwrap()]),
+            ]),
+        )]);
+        assert_eq!(
+            json!([
+                { "package": "package", "crate": "integration", "test": "c" },
+                { "package": "package", "crate": "lib", "test": "tests::a" },
+            ]),
+            tests_json(&test_map)
+        );
+    }
+
+    #[test]
+    fn coverage_map_lines_are_sorted() {
+        let tempdir = tempdir().unwrap();
+        let path = tempdir.path().join("coverage.json");
+        let coverage_map = PackageCrateMap::<BTreeMap<Test, PathCoverageMap>>::from([(
+            String::from("package"),
+            BTreeMap::from([(
+                String::from("lib"),
+                BTreeMap::from([
+                    (
+                        "tests::a".parse().unwrap(),
+                        PathCoverageMap::from([
+                            (String::from("src/lib.rs"), [3, 1, 2].into()),
+                            (String::from("src/main.rs"), [2].into()),
+                        ]),
+                    ),
+                    (
+                        "tests::b".parse().unwrap(),
+                        PathCoverageMap::from([(String::from("src/lib.rs"), [5, 4].into())]),
+                    ),
+                ]),
+            )]),
+        )]);
+        write_coverage_map(&path, &coverage_map).unwrap();
+
+        let value = serde_json::from_str::<Value>(&read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(
+            json!({
+                "package": {
+                    "lib": {
+                        "tests::a": { "src/lib.rs": [1, 2, 3], "src/main.rs": [2] },
+                        "tests::b": { "src/lib.rs": [4, 5] },
+                    },
+                },
+            }),
+            value
+        );
+    }
+}
@@ -0,0 +1,176 @@
+use crate::{coverage_report::percentage, db, output, util};
+use anyhow::Result;
+use cargo_metadata::Metadata;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env::current_dir,
+    path::Path,
+};
+
+/// The lines of a package's files that are instrumented, and those that some test executed.
+#[derive(Default)]
+struct Lines {
+    instrumented: usize,
+    executed: usize,
+}
+
+/// Prints each package whose line coverage, i.e., the percentage of its instrumented lines that
+/// some test executed, is below `threshold`.
+pub(crate) fn report_packages_below(threshold: f64) -> Result<()> {
+    let db = db::read()?;
+    let line_count_map = db.line_count_map()?;
+
+    let mut path_lines_map = BTreeMap::<&String, (BTreeSet<u32>, BTreeSet<u32>)>::new();
+    for path_line_count_map in line_count_map
+        .values()
+        .flat_map(BTreeMap::values)
+        .flat_map(BTreeMap::values)
+    {
+        for (path, line_count_map) in path_line_count_map {
+            let (instrumented, executed) = path_lines_map.entry(path).or_default();
+            for (&line, &count) in line_count_map {
+                instrumented.insert(line);
+                if count != 0 {
+                    executed.insert(line);
+                }
+            }
+        }
+    }
+
+    let metadata = util::metadata_command().exec()?;
+    let package_lines_map = package_lines_map(&metadata, &current_dir()?, &path_lines_map);
+
+    for (package, lines) in package_lines_map {
+        if lines.instrumented == 0 || !is_below(&lines, threshold) {
+            continue;
+        }
+        output::println(&format!(
+            "{package}: {}",
+            percentage(lines.executed, lines.instrumented)
+        ))?;
+    }
+
+    Ok(())
+}
+
+/// Returns the numbers of instrumented and executed lines of each package's files, given the
+/// instrumented and executed lines of each file. The files' paths are relative to `dir`.
+//
+// smoelius: A file is attributed to the package whose directory most closely contains it, no matter
+// which package's tests executed it. Files outside every package (e.g., dependencies' sources) are
+// not counted. A package none of whose files appear in any coverage file has no instrumented lines,
+// so its coverage is unknown, and it is not listed.
+fn package_lines_map<'a>(
+    metadata: &'a Metadata,
+    dir: &Path,
+    path_lines_map: &BTreeMap<&String, (BTreeSet<u32>, BTreeSet<u32>)>,
+) -> BTreeMap<&'a str, Lines> {
+    let mut package_lines_map = BTreeMap::<&str, Lines>::new();
+    for (path, (instrumented, executed)) in path_lines_map {
+        let Some(package) = util::owning_package(metadata, &dir.join(path)) else {
+            continue;
+        };
+        let lines = package_lines_map.entry(&package.name).or_default();
+        lines.instrumented += instrumented.len();
+        lines.executed += executed.len();
+    }
+    package_lines_map
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn is_below(lines: &Lines, threshold: f64) -> bool {
+    (lines.executed as f64 * 100.0 / lines.instrumented as f64) < threshold
+}
+
+#[cfg(test)]
+mod test {
+    use super::package_lines_map;
+    use cargo_metadata::{Metadata, MetadataCommand};
+    use std::{
+        collections::{BTreeMap, BTreeSet},
+        fs::{create_dir_all, write},
+        path::{Path, PathBuf},
+    };
+    use tempfile::{tempdir, TempDir};
+
+    #[test]
+    fn files_are_attributed_to_nearest_enclosing_package() {
+        let (_tempdir, root, metadata) = nested_packages();
+
+        let outer = String::from("src/lib.rs");
+        let inner = String::from("inner/src/lib.rs");
+        let path_lines_map = BTreeMap::from([
+            (&outer, lines(&[1, 2, 3, 4], &[1, 2])),
+            (&inner, lines(&[1, 2], &[1, 2])),
+        ]);
+
+        let package_lines_map = package_lines_map(&metadata, &root, &path_lines_map);
+
+        // smoelius: `inner`'s file lies within `outer`'s directory too, but is attributed only to
+        // `inner`.
+        assert_eq!(
+            vec!["inner", "outer"],
+            package_lines_map.keys().copied().collect::<Vec<_>>()
+        );
+        assert_eq!(2, package_lines_map["inner"].instrumented);
+        assert_eq!(2, package_lines_map["inner"].executed);
+        assert_eq!(4, package_lines_map["outer"].instrumented);
+        assert_eq!(2, package_lines_map["outer"].executed);
+    }
+
+    #[test]
+    fn files_outside_every_package_are_not_counted() {
+        let (_tempdir, root, metadata) = nested_packages();
+
+        let outer = String::from("src/lib.rs");
+        let outside = String::from("/elsewhere/src/lib.rs");
+        let path_lines_map = BTreeMap::from([
+            (&outer, lines(&[1, 2], &[1])),
+            (&outside, lines(&[1, 2, 3], &[])),
+        ]);
+
+        let package_lines_map = package_lines_map(&metadata, &root, &path_lines_map);
+
+        assert_eq!(
+            vec!["outer"],
+            package_lines_map.keys().copied().collect::<Vec<_>>()
+        );
+        assert_eq!(2, package_lines_map["outer"].instrumented);
+        assert_eq!(1, package_lines_map["outer"].executed);
+    }
+
+    /// Returns a workspace whose root package, `outer`, contains another package, `inner`, in its
+    /// `inner` subdirectory. The workspace's directory is returned canonicalized, as cargo reports
+    /// the packages' manifest paths.
+    fn nested_packages() -> (TempDir, PathBuf, Metadata) {
+        let tempdir = tempdir().unwrap();
+        let root = tempdir.path().canonicalize().unwrap();
+        write_package(&root, "outer", "[workspace]\nmembers = [\"inner\"]\n");
+        write_package(&root.join("inner"), "inner", "");
+        let metadata = MetadataCommand::new()
+            .manifest_path(root.join("Cargo.toml"))
+            .no_deps()
+            .exec()
+            .unwrap();
+        (tempdir, root, metadata)
+    }
+
+    fn write_package(dir: &Path, name: &str, extra: &str) {
+        create_dir_all(dir.join("src")).unwrap();
+        write(
+            dir.join("Cargo.toml"),
+            format!(
+                "[package]\nname = \"{name}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n{extra}"
+            ),
+        )
+        .unwrap();
+        write(dir.join("src/lib.rs"), "").unwrap();
+    }
+
+    fn lines(instrumented: &[u32], executed: &[u32]) -> (BTreeSet<u32>, BTreeSet<u32>) {
+        (
+            instrumented.iter().copied().collect(),
+            executed.iter().copied().collect(),
+        )
+    }
+}
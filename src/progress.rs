@@ -1,12 +1,24 @@
 // smoelius: Based on:
 // https://github.com/trailofbits/cargo-unmaintained/blob/4a6a4473f04a2dd54173fe6b84958f50ffad7a7d/src/progress.rs
 
-use std::io::Write;
+use std::{
+    io::{stderr, Stderr, Write},
+    sync::{Mutex, MutexGuard, PoisonError},
+};
 
 use anyhow::{Context, Result};
 
-pub struct Progress {
-    n: usize,
+// smoelius: The state is behind a `Mutex` so that a `Progress` can be shared among threads. Each
+// method holds the lock for its duration, so concurrent calls produce whole frames.
+pub struct Progress<W: Write = Stderr>(Mutex<State<W>>);
+
+/// The characters a spinner cycles through.
+const FRAMES: [char; 4] = ['|', '/', '-', '\\'];
+
+struct State<W> {
+    writer: W,
+    /// The number of steps, or `None` for a spinner, which has no denominator.
+    n: Option<usize>,
     i: usize,
     width_n: usize,
     width_prev: usize,
@@ -14,66 +26,183 @@ pub struct Progress {
     finished: bool,
 }
 
-impl Drop for Progress {
+impl<W: Write> Drop for Progress<W> {
     fn drop(&mut self) {
-        if !self.finished {
-            self.finish().unwrap_or_default();
+        let state = self.0.get_mut().unwrap_or_else(PoisonError::into_inner);
+        if !state.finished {
+            state.finish().unwrap_or_default();
         }
     }
 }
 
 impl Progress {
     pub fn new(n: usize) -> Self {
-        Self {
+        Self::with_writer(n, stderr())
+    }
+
+    /// Returns a `Progress` for when the number of steps is not known in advance, e.g., while
+    /// tests are being listed. Each step advances a spinner rather than a count.
+    pub fn spinner() -> Self {
+        Self::spinner_with_writer(stderr())
+    }
+}
+
+impl<W: Write> Progress<W> {
+    /// Like [`Progress::new`], but draws to `writer` rather than to standard error.
+    pub fn with_writer(n: usize, writer: W) -> Self {
+        Self::with_state(Some(n), writer)
+    }
+
+    /// Like [`Progress::spinner`], but draws to `writer` rather than to standard error.
+    pub fn spinner_with_writer(writer: W) -> Self {
+        Self::with_state(None, writer)
+    }
+
+    fn with_state(n: Option<usize>, writer: W) -> Self {
+        Self(Mutex::new(State {
+            writer,
             n,
             i: 0,
-            width_n: n.to_string().len(),
+            width_n: n.map_or(0, |n| n.to_string().len()),
             width_prev: 0,
             newline_needed: false,
             finished: false,
-        }
+        }))
     }
 
-    pub fn advance(&mut self, msg: &str) -> Result<()> {
-        self.draw(msg)?;
-        assert!(self.i < self.n);
-        self.i += 1;
+    pub fn advance(&self, msg: &str) -> Result<()> {
+        let mut state = self.lock();
+        state.draw(msg)?;
+        assert!(state.n.is_none_or(|n| state.i < n));
+        state.i += 1;
         Ok(())
     }
 
-    pub fn finish(&mut self) -> Result<()> {
-        self.draw("")?;
-        self.newline();
+    pub fn finish(&self) -> Result<()> {
+        self.lock().finish()
+    }
+
+    pub fn newline(&self) {
+        self.lock().newline();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State<W>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<W: Write> State<W> {
+    // smoelius: A finished bar is left showing 100%. A finished spinner has nothing worth showing,
+    // so its line is cleared.
+    fn finish(&mut self) -> Result<()> {
+        if self.n.is_some() {
+            self.draw("")?;
+            self.newline();
+        } else {
+            self.write("")?;
+            self.newline_needed = false;
+        }
         self.finished = true;
         Ok(())
     }
 
-    pub fn newline(&mut self) {
+    fn newline(&mut self) {
         if self.newline_needed {
-            eprintln!();
+            writeln!(self.writer).unwrap_or_default();
         }
         self.newline_needed = false;
     }
 
     fn draw(&mut self, msg: &str) -> Result<()> {
-        assert!(self.i < self.n || msg.is_empty());
-        let width_n = self.width_n;
-        let percent = format!(
-            "({}%)",
-            if self.n == 0 {
-                100
-            } else {
-                self.i * 100 / self.n
-            }
-        );
-        let formatted_msg = format!("{:>width_n$}/{} {percent:>5} {msg}", self.i, self.n,);
+        let formatted_msg = if let Some(n) = self.n {
+            assert!(self.i < n || msg.is_empty());
+            let width_n = self.width_n;
+            let percent = format!("({}%)", (self.i * 100).checked_div(n).unwrap_or(100));
+            format!("{:>width_n$}/{n} {percent:>5} {msg}", self.i)
+        } else {
+            format!("{} {msg}", FRAMES[self.i % FRAMES.len()])
+        };
+        self.write(&formatted_msg)?;
+        self.newline_needed = true;
+        Ok(())
+    }
+
+    /// Writes `formatted_msg` over the previous frame, followed by a carriage return.
+    fn write(&mut self, formatted_msg: &str) -> Result<()> {
         let width_to_overwrite = self.width_prev.saturating_sub(formatted_msg.len());
-        eprint!("{formatted_msg}{:width_to_overwrite$}\r", "");
-        std::io::stderr()
+        write!(self.writer, "{formatted_msg}{:width_to_overwrite$}\r", "")
+            .with_context(|| "failed to write progress")?;
+        self.writer
             .flush()
-            .with_context(|| "failed to flush stderr")?;
+            .with_context(|| "failed to flush progress")?;
         self.width_prev = formatted_msg.len();
-        self.newline_needed = true;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::Progress;
+    use std::thread::scope;
+
+    #[test]
+    fn frames() {
+        let mut buf = Vec::new();
+        let progress = Progress::with_writer(2, &mut buf);
+        progress.advance("first").unwrap();
+        progress.advance("second test").unwrap();
+        progress.finish().unwrap();
+        drop(progress);
+        assert_eq!(
+            "0/2  (0%) first\r1/2 (50%) second test\r2/2 (100%)           \r\n",
+            String::from_utf8(buf).unwrap()
+        );
+    }
+
+    #[test]
+    fn spinner_frames() {
+        let mut buf = Vec::new();
+        let progress = Progress::spinner_with_writer(&mut buf);
+        for msg in ["a", "b", "c", "d", "eee"] {
+            progress.advance(msg).unwrap();
+        }
+        progress.finish().unwrap();
+        drop(progress);
+        assert_eq!(
+            "| a\r/ b\r- c\r\\ d\r| eee\r     \r",
+            String::from_utf8(buf).unwrap()
+        );
+    }
+
+    #[test]
+    fn threads() {
+        const N_THREADS: usize = 8;
+        const N_PER_THREAD: usize = 100;
+
+        let progress = Progress::with_writer(N_THREADS * N_PER_THREAD, Vec::new());
+        scope(|scope| {
+            for _ in 0..N_THREADS {
+                scope.spawn(|| {
+                    for _ in 0..N_PER_THREAD {
+                        progress.advance("test").unwrap();
+                    }
+                });
+            }
+        });
+        progress.finish().unwrap();
+
+        let state = progress.lock();
+        assert_eq!(state.n, Some(state.i));
+        let output = String::from_utf8(state.writer.clone()).unwrap();
+        // smoelius: Every frame is whole, and the last one shows completion.
+        let frames = output
+            .trim_end_matches('\n')
+            .split_terminator('\r')
+            .collect::<Vec<_>>();
+        assert_eq!(N_THREADS * N_PER_THREAD + 1, frames.len());
+        assert!(frames[..frames.len() - 1]
+            .iter()
+            .all(|frame| frame.ends_with("%) test")));
+        assert!(frames.last().unwrap().starts_with("800/800 (100%)"));
+    }
+}
@@ -1,7 +1,8 @@
 use std::{
     cmp::{max, min},
     collections::BTreeSet,
-    ops::{Add, Range},
+    fmt::Display,
+    ops::{Add, Range, Sub},
 };
 
 #[derive(Clone, Eq, PartialEq)]
@@ -27,7 +28,7 @@ impl<T: Ord> PartialOrd for DisjointRange<T> {
     }
 }
 
-#[derive(Clone, Default, Debug)]
+#[derive(Clone, Default, Debug, Eq, PartialEq)]
 pub struct RangeSet<T>(BTreeSet<DisjointRange<T>>);
 
 #[allow(private_bounds)]
@@ -52,6 +53,85 @@ impl<T: Add<Output = T> + Clone + One + Ord> RangeSet<T> {
         self.0 = new_range_set;
     }
 
+    /// Inserts every value in `other` into `self`.
+    pub fn union(&mut self, other: Self) {
+        for DisjointRange(range) in other.0 {
+            self.insert_range(range);
+        }
+    }
+
+    /// Returns the set's disjoint ranges in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = &Range<T>> {
+        self.0.iter().map(|DisjointRange(range)| range)
+    }
+
+    /// Returns whether `self` and `other` have no values in common.
+    pub fn is_disjoint(&self, other: &Self) -> bool {
+        let mut xs = self.iter().peekable();
+        let mut ys = other.iter().peekable();
+        while let (Some(x), Some(y)) = (xs.peek(), ys.peek()) {
+            if x.start < y.end && y.start < x.end {
+                return false;
+            }
+            if x.end < y.end {
+                xs.next();
+            } else {
+                ys.next();
+            }
+        }
+        true
+    }
+
+    /// Returns the values in both `self` and `other`.
+    pub fn intersection(&self, other: &Self) -> Self {
+        let mut range_set = BTreeSet::new();
+        let mut xs = self.iter().peekable();
+        let mut ys = other.iter().peekable();
+        while let (Some(x), Some(y)) = (xs.peek(), ys.peek()) {
+            let start = max(&x.start, &y.start);
+            let end = min(&x.end, &y.end);
+            if start < end {
+                range_set.insert(DisjointRange(start.clone()..end.clone()));
+            }
+            if x.end < y.end {
+                xs.next();
+            } else {
+                ys.next();
+            }
+        }
+        Self(range_set)
+    }
+
+    /// Returns the values in `self` but not in `other`.
+    pub fn difference(&self, other: &Self) -> Self {
+        let mut range_set = BTreeSet::new();
+        let mut ys = other.iter().peekable();
+        for x in self.iter() {
+            let mut start = x.start.clone();
+            while let Some(y) = ys.peek() {
+                if y.end <= start {
+                    ys.next();
+                    continue;
+                }
+                if x.end <= y.start {
+                    break;
+                }
+                if start < y.start {
+                    range_set.insert(DisjointRange(start..y.start.clone()));
+                }
+                start = y.end.clone();
+                if x.end <= start {
+                    break;
+                }
+                ys.next();
+            }
+            if start < x.end {
+                range_set.insert(DisjointRange(start..x.end.clone()));
+            }
+        }
+        Self(range_set)
+    }
+
     pub fn is_empty(&self) -> bool {
         self.0.is_empty()
     }
@@ -85,14 +165,23 @@ impl<T: Add<Output = T> + Clone + One + Ord> RangeSet<T> {
 
     fn find_disjoint_range(&self, value: &T) -> Option<DisjointRange<T>> {
         let value_succ = value.clone() + T::one();
-        let singleton = DisjointRange(value.clone()..value_succ.clone());
-        let mut disjoint_range_iter = self.0.range(singleton.clone()..=singleton);
-        let disjoint_range = disjoint_range_iter.next().cloned()?;
+        let singleton = DisjointRange(value.clone()..value_succ);
+        // smoelius: The first disjoint range whose `end` exceeds `value` is the only one that could
+        // contain `value`.
+        let disjoint_range = self.0.range(singleton..).next().cloned()?;
 
-        // smoelius: `disjoint_range_iter` should match at most one disjoint range.
-        debug_assert!(disjoint_range_iter.next().is_none());
+        if disjoint_range.0.start <= *value {
+            Some(disjoint_range)
+        } else {
+            None
+        }
+    }
+}
 
-        Some(disjoint_range)
+impl RangeSet<u32> {
+    /// Returns the number of values in the set.
+    pub fn len(&self) -> usize {
+        self.0.iter().map(|DisjointRange(range)| range.len()).sum()
     }
 }
 
@@ -110,6 +199,35 @@ fn union<T: Clone + Ord>(x: Range<T>, y: Range<T>) -> Range<T> {
     min(x.start, y.start)..max(x.end, y.end)
 }
 
+// smoelius: Inserting values one at a time would be quadratic, because `insert_range` rebuilds the
+// set. Sorting the values first allows consecutive ones to be coalesced in a single pass, e.g.,
+// when converting a test's covered lines from a `HashSet`.
+#[allow(private_bounds)]
+impl<T: Add<Output = T> + Clone + One + Ord> FromIterator<T> for RangeSet<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut values = iter.into_iter().collect::<Vec<_>>();
+        values.sort_unstable();
+        let mut range_set = BTreeSet::new();
+        let mut current: Option<Range<T>> = None;
+        for value in values {
+            match current.as_mut() {
+                Some(range) if value < range.end => {}
+                Some(range) if value == range.end => range.end = value + T::one(),
+                _ => {
+                    let value_succ = value.clone() + T::one();
+                    if let Some(range) = current.replace(value..value_succ) {
+                        range_set.insert(DisjointRange(range));
+                    }
+                }
+            }
+        }
+        if let Some(range) = current {
+            range_set.insert(DisjointRange(range));
+        }
+        Self(range_set)
+    }
+}
+
 impl<T> IntoIterator for RangeSet<T> {
     type Item = Range<T>;
     // smoelius: Use of `Vec` here is ugly.
@@ -123,6 +241,26 @@ impl<T> IntoIterator for RangeSet<T> {
     }
 }
 
+// smoelius: Ranges are displayed inclusively, using the syntax of a line specification's `<GROUP>`,
+// e.g., `95-97,99`.
+#[allow(private_bounds)]
+impl<T: Clone + Display + One + PartialEq + Sub<Output = T>> Display for RangeSet<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, DisjointRange(range)) in self.0.iter().enumerate() {
+            if i != 0 {
+                f.write_str(",")?;
+            }
+            let last = range.end.clone() - T::one();
+            if range.start == last {
+                write!(f, "{last}")?;
+            } else {
+                write!(f, "{}-{last}", range.start)?;
+            }
+        }
+        Ok(())
+    }
+}
+
 trait One {
     fn one() -> Self;
 }
@@ -132,3 +270,66 @@ impl One for u32 {
         1
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::RangeSet;
+    use proptest::{collection::hash_set, proptest};
+    use std::collections::HashSet;
+
+    #[test]
+    fn contains() {
+        let mut range_set = RangeSet::default();
+        range_set.insert_range(95..98);
+        range_set.insert_range(99..100);
+        assert!(!range_set.contains(94));
+        assert!(range_set.contains(95));
+        assert!(range_set.contains(96));
+        assert!(range_set.contains(97));
+        assert!(!range_set.contains(98));
+        assert!(range_set.contains(99));
+        assert!(!range_set.contains(100));
+    }
+
+    #[test]
+    fn remove() {
+        let mut range_set = RangeSet::default();
+        range_set.insert_range(95..100);
+        assert!(range_set.remove(97));
+        assert!(!range_set.remove(97));
+        assert_eq!("95-96,98-99", range_set.to_string());
+    }
+
+    #[test]
+    fn contiguous_hash_set_is_single_range() {
+        let hash_set = (95..100).rev().collect::<HashSet<u32>>();
+        let range_set = hash_set.iter().copied().collect::<RangeSet<_>>();
+        assert_eq!(vec![95..100], range_set.into_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn from_iter_coalesces_duplicates_and_gaps() {
+        let range_set = [99, 95, 96, 96, 97].into_iter().collect::<RangeSet<u32>>();
+        assert_eq!("95-97,99", range_set.to_string());
+    }
+
+    proptest! {
+        #[test]
+        fn set_operations_agree_with_hash_set(
+            xs in hash_set(0..64u32, 0..32),
+            ys in hash_set(0..64u32, 0..32),
+        ) {
+            let x_range_set = xs.iter().copied().collect::<RangeSet<_>>();
+            let y_range_set = ys.iter().copied().collect::<RangeSet<_>>();
+            assert_eq!(xs.is_disjoint(&ys), x_range_set.is_disjoint(&y_range_set));
+            assert_eq!(
+                xs.intersection(&ys).copied().collect::<RangeSet<_>>().to_string(),
+                x_range_set.intersection(&y_range_set).to_string()
+            );
+            assert_eq!(
+                xs.difference(&ys).copied().collect::<RangeSet<_>>().to_string(),
+                x_range_set.difference(&y_range_set).to_string()
+            );
+        }
+    }
+}
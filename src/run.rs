@@ -1,13 +1,18 @@
-use crate::{opts, progress::Progress, warn, PackageCrateMap, Test, CTRLC};
-use anyhow::{bail, ensure, Result};
+use crate::{
+    db::layout, opts, output, progress::Progress, util, warn, Format, PackageCrateMap, Test, CTRLC,
+};
+use anyhow::{anyhow, bail, ensure, Context, Result};
 use assert_cmd::output::OutputError;
+use cargo_metadata::Message;
+use once_cell::sync::OnceCell;
 use std::{
     cmp::max,
-    env::var,
-    fs::create_dir_all,
+    env::{current_dir, var, var_os},
+    ffi::OsStr,
+    fs::{create_dir_all, read_dir, read_to_string, remove_file, write},
     io::{stderr, IsTerminal},
-    path::Path,
-    process::Command,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
     sync::atomic::Ordering,
 };
 
@@ -15,59 +20,70 @@ pub(crate) fn run_tests(
     package_crate_test_map: &PackageCrateMap<Vec<Test>>,
     coverage: bool,
 ) -> Result<()> {
-    let mut package_width = 0;
-    let mut crate_width = 0;
-    let mut test_width = 0;
-    let mut n = 0;
+    let (package_width, crate_width, test_width, n) = widths_and_count(package_crate_test_map);
 
-    for (package, crate_test_map) in package_crate_test_map {
-        package_width = max(package_width, package.len());
-        for (krate, tests) in crate_test_map {
-            crate_width = max(crate_width, krate.len());
-            for test in tests {
-                test_width = max(test_width, test.to_string().len());
-            }
-            n += tests.len();
-        }
-    }
+    // smoelius: Under --no-run, nothing is executed and nothing is written to line-test.db. So,
+    // e.g., `--refresh --no-run` merely shows the tests that would be refreshed.
+    let dry_run = opts::get().no_run;
+
+    // smoelius: With --batch, each crate's test binary is built once, and each test is then run
+    // directly with that binary (see `build_test_binary`). Otherwise, each test is run with its own
+    // `cargo llvm-cov` command.
+    let batched = coverage && !dry_run && opts::get().batch;
 
-    let mut progress = if stderr().is_terminal() && coverage && !opts::get().verbose {
+    // smoelius: With --clean, `cargo llvm-cov` removes the profraw files itself.
+    let profraw_dir = (coverage && !dry_run && !opts::get().clean)
+        .then(profraw_dir)
+        .transpose()?;
+
+    let progress = if show_progress() && coverage && !dry_run && !opts::get().verbose {
         Some(Progress::new(n))
     } else {
         None
     };
 
-    let path = Path::new("line-test.db/packages");
+    // smoelius: TAP output describes the selected tests' results, not the runs used to build
+    // coverage.
+    let tap = opts::get().format == Format::Tap && !coverage;
+    let mut tap_index = 0;
+
+    if tap {
+        output::println(&format!("1..{n}"))?;
+    }
+
     for (package, crate_test_map) in package_crate_test_map {
         if CTRLC.load(Ordering::SeqCst) {
             bail!("ctrl-c detected");
         }
 
-        let path_buf = path.join(package);
-
         for (krate, tests) in crate_test_map {
             if CTRLC.load(Ordering::SeqCst) {
                 bail!("ctrl-c detected");
             }
 
-            let path_buf = path_buf.join(krate);
-
             if tests.is_empty() {
                 continue;
             }
 
-            if coverage {
-                create_dir_all(&path_buf).unwrap_or_default();
+            if coverage && !dry_run {
+                create_dir_all(layout::crate_dir(layout::root(), package, krate))
+                    .unwrap_or_default();
             }
 
+            let test_binary = if batched {
+                build_test_binary(package, krate, progress.as_ref())?
+            } else {
+                None
+            };
+
             for test in tests {
                 if CTRLC.load(Ordering::SeqCst) {
                     bail!("ctrl-c detected");
                 }
 
-                let path_buf = path_buf.join(test.to_string()).with_extension("lcov");
+                let path_buf = layout::lcov_path(layout::root(), package, krate, test);
 
-                if let Some(progress) = progress.as_mut() {
+                if let Some(progress) = progress.as_ref() {
                     progress.advance(&format!(
                         "package: {:package_width$}  crate: {:crate_width$}  test: {:test_width$}",
                         package,
@@ -76,87 +92,556 @@ pub(crate) fn run_tests(
                     ))?;
                 }
 
-                // smoelius: Passing --no-clean to `cargo llvm-cov` makes successively running tests
-                // from the same crate faster. However, it leaves around profraw files, which cause
+                // smoelius: A test binary that failed to build was warned about, and its tests
+                // produce no coverage files.
+                if batched && test_binary.is_none() {
+                    continue;
+                }
+
+                // smoelius: Both --no-clean and `cargo llvm-cov report` leave around profraw files.
+                // A profraw file left by an earlier test would be attributed to this one, causing
                 // false positive coverage reports. So, remove the profraw files. See:
                 // https://github.com/taiki-e/cargo-llvm-cov/pull/385
-                if coverage {
-                    remove_profraw_files()?;
+                if let Some(profraw_dir) = &profraw_dir {
+                    clean_profraw_dir(profraw_dir)?;
                 }
 
-                let mut command = cargo_command(
-                    package,
-                    krate,
-                    if coverage { Some(&path_buf) } else { None },
-                );
-                command.args(["--", "--exact", &test.to_string()]);
+                let mut command = if let Some(test_binary) = &test_binary {
+                    test_binary_command(test_binary, test)?
+                } else {
+                    cargo_command(
+                        package,
+                        krate,
+                        if coverage { Some(&path_buf) } else { None },
+                        &["--exact", &test.to_string()],
+                    )?
+                };
+                command.envs(opts::get().env.iter().map(|(key, value)| (key, value)));
 
-                if opts::get().show_commands {
-                    if let Some(progress) = progress.as_mut() {
-                        progress.newline();
+                show_command(&command, progress.as_ref())?;
+
+                if dry_run {
+                    if tap {
+                        tap_index += 1;
+                        output::println(&format!(
+                            "ok {tap_index} - {package} {krate} {test} # SKIP --no-run"
+                        ))?;
                     }
-                    println!("{command:?}");
+                    continue;
                 }
 
-                if opts::get().no_run {
-                    continue;
+                let success = run_command(&mut command, progress.as_ref())?;
+
+                // smoelius: As with `cargo llvm-cov`, a test that fails produces no coverage file.
+                if test_binary.is_some() && success {
+                    let mut command = report_command(package, &path_buf);
+                    show_command(&command, progress.as_ref())?;
+                    run_command(&mut command, progress.as_ref())?;
                 }
 
-                if opts::get().verbose {
-                    let status = command.status()?;
-                    if !status.success() {
-                        if let Some(progress) = progress.as_mut() {
-                            progress.newline();
-                        }
-                        warn(&format!("command failed: {command:?}"))?;
-                    }
-                } else {
-                    let output = command.output()?;
-                    if !output.status.success() {
-                        // smoelius: Note that `progress` is necessarily `None` when --verbose is
-                        // used.
-                        warn(&format!(
-                            "command failed: {command:?}\n{}",
-                            OutputError::new(output)
-                        ))?;
-                    }
+                if coverage {
+                    relativize_lcov(&path_buf)?;
+                }
+
+                if tap {
+                    tap_index += 1;
+                    output::println(&format!(
+                        "{}ok {tap_index} - {package} {krate} {test}",
+                        if success { "" } else { "not " }
+                    ))?;
                 }
             }
         }
     }
 
-    if let Some(progress) = progress.as_mut() {
+    if let Some(progress) = progress.as_ref() {
         progress.finish()?;
     }
 
     Ok(())
 }
 
-fn remove_profraw_files() -> Result<()> {
-    let mut command = Command::new("cargo");
-    command.args(["llvm-cov", "clean", "--profraw-only"]);
-    let status = command.status()?;
-    ensure!(status.success(), "command failed: {command:?}");
+/// An instrumented test binary, and the directory of the package it belongs to.
+struct TestBinary {
+    path: PathBuf,
+    manifest_dir: PathBuf,
+}
+
+/// Builds `krate`'s test binary with coverage instrumentation. Returns `None` if the build fails,
+/// which is warned about.
+//
+// smoelius: Running `cargo llvm-cov` once per test, even with --no-clean, has cargo check whether
+// the test binary is fresh and has `cargo llvm-cov` set up its environment, for every test.
+// Building the binary once and running it directly avoids that overhead. The binary is built under
+// the environment reported by `cargo llvm-cov show-env`, so `cargo llvm-cov report` can produce
+// each test's lcov file afterward.
+fn build_test_binary(
+    package: &str,
+    krate: &str,
+    progress: Option<&Progress>,
+) -> Result<Option<TestBinary>> {
+    let cargo = var("CARGO").unwrap_or_else(|_| String::from("cargo"));
+    let mut command = Command::new(cargo);
+    command.args([
+        "test",
+        "--no-run",
+        "--message-format=json",
+        "--package",
+        package,
+    ]);
+    command.args(test_selection(krate));
+    cargo_options(&mut command)?;
+    command.envs(coverage_env()?.iter().map(|(key, value)| (key, value)));
+
+    show_command(&command, progress)?;
+
+    let output = command
+        .stderr(if opts::get().verbose {
+            Stdio::inherit()
+        } else {
+            Stdio::piped()
+        })
+        .output()
+        .with_context(|| format!("failed to spawn command: {command:?}"))?;
+    if !output.status.success() {
+        if let Some(progress) = progress {
+            progress.newline();
+        }
+        warn(&format!(
+            "test command failed: {command:?}\n{}",
+            OutputError::new(output)
+        ))?;
+        return Ok(None);
+    }
+
+    let mut test_binary = None;
+    for result in Message::parse_stream(output.stdout.as_slice()) {
+        let Message::CompilerArtifact(artifact) = result? else {
+            continue;
+        };
+        let (true, Some(executable)) = (artifact.profile.test, artifact.executable) else {
+            continue;
+        };
+        let manifest_dir = artifact
+            .manifest_path
+            .parent()
+            .ok_or_else(|| anyhow!("manifest path has no parent: {}", artifact.manifest_path))?;
+        test_binary = Some(TestBinary {
+            path: executable.into_std_path_buf(),
+            manifest_dir: manifest_dir.as_std_path().to_path_buf(),
+        });
+    }
+    test_binary
+        .map(Some)
+        .ok_or_else(|| anyhow!("command produced no test binary: {command:?}"))
+}
+
+/// Returns a command that runs `test` with `test_binary`, writing a profraw file where
+/// `cargo llvm-cov report` will find it.
+fn test_binary_command(test_binary: &TestBinary, test: &Test) -> Result<Command> {
+    // smoelius: The runner is passed the test binary's path.
+    let mut command = if let Some(runner_cmd) = &opts::get().runner_cmd {
+        let (program, args) = split_runner(runner_cmd)?;
+        let mut command = Command::new(program);
+        command.args(args);
+        command.arg(&test_binary.path);
+        command
+    } else {
+        Command::new(&test_binary.path)
+    };
+    // smoelius: Like `cargo test`, run the test binary from its package's directory.
+    command.current_dir(&test_binary.manifest_dir);
+    command.env("CARGO_MANIFEST_DIR", &test_binary.manifest_dir);
+    command.envs(
+        coverage_env()?
+            .iter()
+            .filter(|(key, _)| key == "LLVM_PROFILE_FILE")
+            .map(|(key, value)| (key, value)),
+    );
+    command.args(test_binary_args());
+    command.args(["--exact", &test.to_string()]);
+    Ok(command)
+}
+
+/// Splits `runner_cmd` into a program and its arguments.
+//
+// smoelius: The runner is split as a shell would split it, so that, e.g., a quoted argument
+// containing spaces is a single word.
+fn split_runner(runner_cmd: &str) -> Result<(String, Vec<String>)> {
+    let mut words = shlex::split(runner_cmd)
+        .ok_or_else(|| anyhow!("failed to parse --runner-cmd: {runner_cmd}"))?
+        .into_iter();
+    let program = words
+        .next()
+        .ok_or_else(|| anyhow!("--runner-cmd is empty"))?;
+    Ok((program, words.collect()))
+}
+
+/// Returns a `cargo llvm-cov report` command that writes the coverage recorded in the profraw
+/// files to `path`.
+fn report_command(package: &str, path: &Path) -> Command {
+    let cargo = var("CARGO").unwrap_or_else(|_| String::from("cargo"));
+    let mut command = Command::new(cargo);
+    command.args([
+        "llvm-cov",
+        "report",
+        "--package",
+        package,
+        "--lcov",
+        "--output-path",
+        &path.to_string_lossy(),
+    ]);
+    if let Some(manifest_path) = &opts::get().manifest_path {
+        command.arg("--manifest-path").arg(manifest_path);
+    }
+    if let Some(profile) = &opts::get().profile {
+        command.args(["--profile", profile]);
+    }
+    let (cargo_args, _) = split_args(&opts::get().zzargs);
+    if let Some(triple) = cargo_target(cargo_args) {
+        command.args(["--target", triple]);
+    }
+    command
+}
+
+/// Returns the environment `cargo llvm-cov` builds and runs tests under, as reported by
+/// `cargo llvm-cov show-env`.
+fn coverage_env() -> Result<&'static [(String, String)]> {
+    static COVERAGE_ENV: OnceCell<Vec<(String, String)>> = OnceCell::new();
+    COVERAGE_ENV
+        .get_or_try_init(|| {
+            let target_dir = profraw_dir()?;
+            let mut command = Command::new("cargo");
+            command.args(["llvm-cov", "show-env"]);
+            // smoelius: `show-env` has no --manifest-path option, so it is run from the
+            // manifest's directory.
+            if !opts::project_dir().as_os_str().is_empty() {
+                command.current_dir(opts::project_dir());
+            }
+            // smoelius: This makes the profraw files go to `target_dir`.
+            command.env("CARGO_LLVM_COV_TARGET_DIR", &target_dir);
+            let output = command
+                .stderr(Stdio::null())
+                .output()
+                .with_context(|| format!("failed to spawn command: {command:?}"))?;
+            ensure!(output.status.success(), "command failed: {command:?}");
+            let mut env = Vec::new();
+            for line in String::from_utf8(output.stdout)?.lines() {
+                let (key, value) = line.split_once('=').ok_or_else(|| {
+                    anyhow!("unexpected `cargo llvm-cov show-env` output: {line}")
+                })?;
+                env.push((key.to_owned(), unquote(value)));
+            }
+            // smoelius: `show-env` expects the binaries to be built in the regular target
+            // directory. Building them in `cargo llvm-cov`'s own target directory instead keeps
+            // instrumented artifacts out of the regular one, and is where `cargo llvm-cov report`
+            // looks for them.
+            let target_dir = target_dir.to_string_lossy();
+            env.retain(|(key, _)| {
+                key != "CARGO_LLVM_COV_TARGET_DIR" && key != "CARGO_LLVM_COV_BUILD_DIR"
+            });
+            for key in [
+                "CARGO_TARGET_DIR",
+                "CARGO_LLVM_COV_TARGET_DIR",
+                "CARGO_LLVM_COV_BUILD_DIR",
+            ] {
+                env.push((key.to_owned(), target_dir.clone().into_owned()));
+            }
+            Ok(env)
+        })
+        .map(Vec::as_slice)
+}
+
+/// Removes the single quotes with which `cargo llvm-cov show-env` quotes a value.
+fn unquote(value: &str) -> String {
+    value
+        .strip_prefix('\'')
+        .and_then(|value| value.strip_suffix('\''))
+        .map_or_else(|| value.to_owned(), |value| value.replace("'\\''", "'"))
+}
+
+fn show_command(command: &Command, progress: Option<&Progress>) -> Result<()> {
+    if opts::get().show_commands && opts::get().format == Format::Human {
+        if let Some(progress) = progress {
+            progress.newline();
+        }
+        output::println(&util::shell_command(command)?)?;
+    }
     Ok(())
 }
 
-pub(crate) fn cargo_command(package: &str, krate: &str, path: Option<&Path>) -> Command {
+/// Returns the widest package, crate, and test names, and the number of tests.
+pub(crate) fn widths_and_count(
+    package_crate_test_map: &PackageCrateMap<Vec<Test>>,
+) -> (usize, usize, usize, usize) {
+    let mut package_width = 0;
+    let mut crate_width = 0;
+    let mut test_width = 0;
+    let mut n = 0;
+
+    for (package, crate_test_map) in package_crate_test_map {
+        package_width = max(package_width, package.len());
+        for (krate, tests) in crate_test_map {
+            crate_width = max(crate_width, krate.len());
+            for test in tests {
+                test_width = max(test_width, test.to_string().len());
+            }
+            n += tests.len();
+        }
+    }
+
+    (package_width, crate_width, test_width, n)
+}
+
+// smoelius: A pseudo-terminal (e.g., in CI) can make `is_terminal` return true even though
+// carriage returns will not be interpreted. Hence, --no-progress and `TERM=dumb` override it.
+pub(crate) fn show_progress() -> bool {
+    !opts::get().no_progress && var("TERM").as_deref() != Ok("dumb") && stderr().is_terminal()
+}
+
+/// Runs `command` and returns whether it succeeded.
+fn run_command(command: &mut Command, progress: Option<&Progress>) -> Result<bool> {
+    // smoelius: A failure to spawn the command (e.g., because cargo could not be found)
+    // is an error. A command that runs but fails (e.g., because the test failed) is
+    // only a warning.
+    if opts::get().verbose {
+        let status = command
+            .status()
+            .with_context(|| format!("failed to spawn command: {command:?}"))?;
+        if !status.success() {
+            if let Some(progress) = progress {
+                progress.newline();
+            }
+            warn(&format!("test command failed: {command:?}"))?;
+        }
+        Ok(status.success())
+    } else {
+        let output = command
+            .output()
+            .with_context(|| format!("failed to spawn command: {command:?}"))?;
+        let success = output.status.success();
+        if !success {
+            // smoelius: Note that `progress` is necessarily `None` when --verbose is
+            // used.
+            warn(&format!(
+                "test command failed: {command:?}\n{}",
+                OutputError::new(output)
+            ))?;
+        }
+        Ok(success)
+    }
+}
+
+// smoelius: `cargo llvm-cov` records absolute paths. Rewriting those within the current directory
+// to be relative to it allows the database to be used after the repository is moved or checked out
+// elsewhere. Symlinks are resolved as well (see `util::resolve_path`). A test that fails to build
+// leaves no coverage file.
+fn relativize_lcov(path: &Path) -> Result<()> {
+    if !path.try_exists()? {
+        return Ok(());
+    }
+    let root = current_dir()?.canonicalize()?;
+    let contents =
+        read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let mut relativized = String::with_capacity(contents.len());
+    for line in contents.split_inclusive('\n') {
+        let Some(source_file) = line.strip_prefix("SF:") else {
+            relativized.push_str(line);
+            continue;
+        };
+        let trimmed = source_file.trim_end_matches(['\r', '\n']);
+        relativized.push_str("SF:");
+        relativized.push_str(&util::resolve_path(&root, trimmed));
+        relativized.push_str(&source_file[trimmed.len()..]);
+    }
+    write(path, relativized).with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// Returns the directory in which `cargo llvm-cov` writes profraw files.
+fn profraw_dir() -> Result<PathBuf> {
+    if let Some(target_dir) = var_os("CARGO_LLVM_COV_TARGET_DIR") {
+        return Ok(PathBuf::from(target_dir));
+    }
+    let metadata = util::metadata_command().exec()?;
+    Ok(metadata
+        .target_directory
+        .join("llvm-cov-target")
+        .into_std_path_buf())
+}
+
+/// Removes the profraw files in `dir`, listing them if --verbose was passed, and warns if any
+/// remain afterward.
+fn clean_profraw_dir(dir: &Path) -> Result<()> {
+    for path in remove_profraw_files(dir)? {
+        if opts::get().verbose {
+            eprintln!("Removed {}", path.display());
+        }
+    }
+    let remaining = profraw_files(dir)?;
+    if !remaining.is_empty() {
+        warn(&format!(
+            "profraw files remain in {} after cleanup, so coverage may be attributed to the wrong \
+             test: {:#?}",
+            dir.display(),
+            remaining
+        ))?;
+    }
+    Ok(())
+}
+
+/// Removes the profraw files in `dir` and returns their paths.
+fn remove_profraw_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let removed = profraw_files(dir)?;
+    for path in &removed {
+        remove_file(path).with_context(|| format!("failed to remove {}", path.display()))?;
+    }
+    Ok(removed)
+}
+
+/// Returns the sorted paths of the profraw files in `dir`, which need not exist.
+fn profraw_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    if !dir.try_exists()? {
+        return Ok(Vec::new());
+    }
+    let mut paths = Vec::new();
+    for result in read_dir(dir)? {
+        let path = result?.path();
+        if path.extension() == Some(OsStr::new("profraw")) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Returns a `cargo test` (or `cargo llvm-cov`, if `path` is `Some`) command for `package` and
+/// `krate`. `test_args` are passed to the test binary.
+pub(crate) fn cargo_command(
+    package: &str,
+    krate: &str,
+    path: Option<&Path>,
+    test_args: &[&str],
+) -> Result<Command> {
     let cargo = var("CARGO").unwrap_or_else(|_| String::from("cargo"));
     let mut command = Command::new(cargo);
     command.arg(if path.is_some() { "llvm-cov" } else { "test" });
     command.args(["--package", package]);
     command.args(test_selection(krate));
     if let Some(path) = path {
+        if !opts::get().clean {
+            command.arg("--no-clean");
+        }
         command.args([
-            "--no-clean",
             "--lcov",
             "--output-path",
             &path.to_string_lossy(),
             // "-vv",
         ]);
     }
-    command.args(&opts::get().zzargs);
-    command
+    cargo_options(&mut command)?;
+    command.arg("--");
+    command.args(test_binary_args());
+    command.args(test_args);
+    Ok(command)
+}
+
+/// Adds the options that apply to every cargo command that builds tests, including the user's
+/// arguments before `--`.
+fn cargo_options(command: &mut Command) -> Result<()> {
+    if let Some(manifest_path) = &opts::get().manifest_path {
+        command.arg("--manifest-path").arg(manifest_path);
+    }
+    // smoelius: `cargo_command` is used both to list tests and to run them, so the feature options
+    // are applied consistently to both.
+    if opts::get().all_features {
+        command.arg("--all-features");
+    }
+    for features in &opts::get().features {
+        command.args(["--features", features]);
+    }
+    // smoelius: A profile changes where `cargo llvm-cov` puts the test binaries (e.g.,
+    // llvm-cov-target/test-fast), but not where it puts the profraw files, which are always written
+    // directly to llvm-cov-target. So `profraw_dir` need not account for the profile.
+    if let Some(profile) = &opts::get().profile {
+        command.args(["--profile", profile]);
+    }
+    // smoelius: The user's arguments before `--` are for cargo; those after are for the test
+    // binary. The test binary's arguments must come after a single `--`, or else a user-supplied
+    // `--` would cause `test_args` to be treated as test names.
+    let (cargo_args, _) = split_args(&opts::get().zzargs);
+    // smoelius: The runner applies to both listing and running tests, since both execute the test
+    // binary.
+    if let Some(runner_cmd) = &opts::get().runner_cmd {
+        command.env(runner_var(cargo_args)?, runner_cmd);
+    }
+    command.args(cargo_args);
+    Ok(())
+}
+
+/// Returns the name of the environment variable that sets the runner for the target that tests are
+/// built for, e.g., `CARGO_TARGET_X86_64_UNKNOWN_LINUX_GNU_RUNNER`.
+fn runner_var(cargo_args: &[String]) -> Result<&'static str> {
+    static RUNNER_VAR: OnceCell<String> = OnceCell::new();
+    RUNNER_VAR
+        .get_or_try_init(|| {
+            let triple = target_triple(cargo_args)?;
+            Ok(format!(
+                "CARGO_TARGET_{}_RUNNER",
+                triple.to_uppercase().replace(['-', '.'], "_")
+            ))
+        })
+        .map(String::as_str)
+}
+
+/// Returns the triple passed to cargo with `--target`, or the host's triple if there is none.
+fn target_triple(cargo_args: &[String]) -> Result<String> {
+    if let Some(triple) = cargo_target(cargo_args) {
+        return Ok(triple.to_owned());
+    }
+    let rustc = var("RUSTC").unwrap_or_else(|_| String::from("rustc"));
+    let output = Command::new(&rustc)
+        .arg("-vV")
+        .output()
+        .with_context(|| format!("failed to run `{rustc} -vV`"))?;
+    ensure!(output.status.success(), "`{rustc} -vV` failed");
+    String::from_utf8(output.stdout)?
+        .lines()
+        .find_map(|line| line.strip_prefix("host: "))
+        .map(ToOwned::to_owned)
+        .ok_or_else(|| anyhow!("`{rustc} -vV` did not report a host triple"))
+}
+
+/// Returns the arguments passed to every test binary, i.e., the user's arguments after `--` and
+/// those implied by options like --include-ignored. They are recorded in line-test.db when it is
+/// built.
+pub(crate) fn test_binary_args() -> Vec<String> {
+    let (_, user_test_args) = split_args(&opts::get().zzargs);
+    let mut args = user_test_args.to_vec();
+    if opts::get().include_ignored {
+        args.push(String::from("--include-ignored"));
+    }
+    args
+}
+
+/// Returns the triple passed to cargo with `--target`, if any.
+fn cargo_target(cargo_args: &[String]) -> Option<&str> {
+    let mut iter = cargo_args.iter();
+    while let Some(arg) = iter.next() {
+        if let Some(triple) = arg.strip_prefix("--target=") {
+            return Some(triple);
+        }
+        if arg == "--target" {
+            return iter.next().map(String::as_str);
+        }
+    }
+    None
+}
+
+fn split_args(args: &[String]) -> (&[String], &[String]) {
+    match args.iter().position(|arg| arg == "--") {
+        Some(i) => (&args[..i], &args[i + 1..]),
+        None => (args, &[]),
+    }
 }
 
 // smoelius: This doesn't have an appreciable effect on performance, and it complicates the output
@@ -180,3 +665,68 @@ pub(crate) fn test_selection(krate: &str) -> Vec<&str> {
         vec!["--test", krate]
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{profraw_files, relativize_lcov, remove_profraw_files, split_runner};
+    use std::{
+        env::current_dir,
+        fs::{read_to_string, write},
+    };
+    use tempfile::tempdir;
+
+    #[test]
+    fn lcov_paths_are_relativized() {
+        let current_dir = current_dir().unwrap();
+        let tempdir = tempdir().unwrap();
+        let lcov = tempdir.path().join("test.lcov");
+        let contents = format!(
+            "SF:{}/src/lib.rs\nDA:1,1\nend_of_record\nSF:/elsewhere/lib.rs\nend_of_record\n",
+            current_dir.display()
+        );
+        write(&lcov, contents).unwrap();
+
+        relativize_lcov(&lcov).unwrap();
+
+        assert_eq!(
+            "SF:src/lib.rs\nDA:1,1\nend_of_record\nSF:/elsewhere/lib.rs\nend_of_record\n",
+            read_to_string(&lcov).unwrap()
+        );
+    }
+
+    #[test]
+    fn profraw_files_are_removed() {
+        let tempdir = tempdir().unwrap();
+        let profraw = tempdir.path().join("my-package-1234-5678.profraw");
+        let other = tempdir.path().join("my-package.profdata");
+        write(&profraw, "").unwrap();
+        write(&other, "").unwrap();
+
+        let removed = remove_profraw_files(tempdir.path()).unwrap();
+        assert_eq!(vec![profraw.clone()], removed);
+        assert!(!profraw.try_exists().unwrap());
+        assert!(other.try_exists().unwrap());
+
+        assert!(profraw_files(tempdir.path()).unwrap().is_empty());
+        assert!(remove_profraw_files(tempdir.path()).unwrap().is_empty());
+        assert!(remove_profraw_files(&tempdir.path().join("nonexistent"))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn runner_is_split_like_shell_words() {
+        assert_eq!(
+            (
+                String::from("/path with spaces/runner"),
+                vec![String::from("-L"), String::from("a b")]
+            ),
+            split_runner("'/path with spaces/runner' -L \"a b\"").unwrap()
+        );
+        assert!(split_runner("'unterminated")
+            .unwrap_err()
+            .to_string()
+            .contains("failed to parse --runner-cmd"));
+        assert!(split_runner(" ").unwrap_err().to_string().contains("empty"));
+    }
+}
@@ -0,0 +1,103 @@
+use crate::PathLineMap;
+use anyhow::{Context, Result};
+use clap::crate_version;
+use serde_json::{json, Value};
+use std::{fs::write, ops::Range, path::Path};
+
+const RULE_ID: &str = "uncovered-changed-line";
+
+/// Writes a SARIF 2.1.0 document to `path` with one result per span of lines in `uncovered`.
+pub(crate) fn write_sarif(path: &Path, uncovered: &PathLineMap) -> Result<()> {
+    let json = serde_json::to_string_pretty(&sarif(uncovered))?;
+    write(path, json).with_context(|| format!("failed to write {}", path.display()))
+}
+
+fn sarif(uncovered: &PathLineMap) -> Value {
+    let mut results = Vec::new();
+    for (path, line_set) in uncovered {
+        for Range { start, end } in line_set.clone() {
+            let end_line = end - 1;
+            results.push(json!({
+                "ruleId": RULE_ID,
+                "level": "warning",
+                "message": {
+                    "text": if start == end_line {
+                        format!("line {start} is not covered by any test")
+                    } else {
+                        format!("lines {start}-{end_line} are not covered by any test")
+                    },
+                },
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": { "uri": path },
+                        "region": { "startLine": start, "endLine": end_line },
+                    },
+                }],
+            }));
+        }
+    }
+
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "cargo-line-test",
+                    "version": crate_version!(),
+                    "informationUri": "https://github.com/trailofbits/cargo-line-test",
+                    "rules": [{
+                        "id": RULE_ID,
+                        "shortDescription": { "text": "Line not covered by any test" },
+                    }],
+                },
+            },
+            "results": results,
+        }],
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{sarif, RULE_ID};
+    use crate::PathLineMap;
+
+    #[test]
+    fn one_result_per_span() {
+        let uncovered =
+            PathLineMap::from([(String::from("src/lib.rs"), [5, 7, 8].into_iter().collect())]);
+        let value = sarif(&uncovered);
+        assert_eq!("2.1.0", value["version"]);
+
+        let runs = value["runs"].as_array().unwrap();
+        assert_eq!(1, runs.len());
+        assert_eq!("cargo-line-test", runs[0]["tool"]["driver"]["name"]);
+        assert_eq!(RULE_ID, runs[0]["tool"]["driver"]["rules"][0]["id"]);
+
+        let results = runs[0]["results"].as_array().unwrap();
+        let regions = results
+            .iter()
+            .map(|result| {
+                assert_eq!(RULE_ID, result["ruleId"]);
+                let locations = result["locations"].as_array().unwrap();
+                assert_eq!(1, locations.len());
+                let physical_location = &locations[0]["physicalLocation"];
+                assert_eq!("src/lib.rs", physical_location["artifactLocation"]["uri"]);
+                let region = &physical_location["region"];
+                (
+                    region["startLine"].as_u64().unwrap(),
+                    region["endLine"].as_u64().unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(vec![(5, 5), (7, 8)], regions);
+        assert_eq!(
+            "line 5 is not covered by any test",
+            results[0]["message"]["text"]
+        );
+        assert_eq!(
+            "lines 7-8 are not covered by any test",
+            results[1]["message"]["text"]
+        );
+    }
+}
@@ -1,10 +1,164 @@
+use crate::{opts, HashAlgo};
 use anyhow::Result;
+use cargo_metadata::{Metadata, MetadataCommand, Package};
+use once_cell::sync::Lazy;
 use sha2::{Digest, Sha256};
-use std::path::Path;
+use std::{
+    collections::HashMap,
+    env::current_dir,
+    fmt::Write,
+    fs::metadata,
+    path::{Component, Path, PathBuf},
+    process::Command,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::SystemTime,
+};
+
+/// Maps each hashed path and algorithm to the path's modification time and size when it was hashed,
+/// and its digest.
+type DigestCache = HashMap<(PathBuf, HashAlgo), (SystemTime, u64, [u8; 32])>;
+
+static DIGEST_CACHE: Lazy<Mutex<DigestCache>> = Lazy::new(Mutex::default);
+
+/// The number of times a file's contents were actually hashed, i.e., not served from the cache.
+static N_HASHED: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the digest of `path`'s contents computed with `hash_algo`. The digest is cached and
+/// reused as long as `path`'s modification time and size are unchanged.
+pub(crate) fn hash_path_contents(path: impl AsRef<Path>, hash_algo: HashAlgo) -> Result<[u8; 32]> {
+    let path = path.as_ref();
+    let metadata = metadata(path)?;
+    let modified = metadata.modified()?;
+    let len = metadata.len();
+
+    if let Some(&(cached_modified, cached_len, digest)) = DIGEST_CACHE
+        .lock()
+        .unwrap()
+        .get(&(path.to_path_buf(), hash_algo))
+    {
+        if cached_modified == modified && cached_len == len {
+            return Ok(digest);
+        }
+    }
 
-pub(crate) fn hash_path_contents(path: impl AsRef<Path>) -> Result<[u8; 32]> {
     let bytes = std::fs::read(path)?;
-    let mut hasher = Sha256::new();
-    hasher.update(bytes);
-    Ok(hasher.finalize().into())
+    let digest = match hash_algo {
+        HashAlgo::Sha256 => Sha256::digest(bytes).into(),
+        HashAlgo::Blake3 => blake3::hash(&bytes).into(),
+    };
+    N_HASHED.fetch_add(1, Ordering::SeqCst);
+
+    DIGEST_CACHE
+        .lock()
+        .unwrap()
+        .insert((path.to_path_buf(), hash_algo), (modified, len, digest));
+
+    Ok(digest)
+}
+
+/// Returns `path`, which is absolute or relative to `root`, as a path relative to `root` with
+/// symlinks resolved. `root` should itself have its symlinks resolved. A path that cannot be
+/// resolved, or that resolves to a location outside `root`, is returned relative to `root` if it is
+/// beneath `root` as written. Otherwise, it is returned absolute with symlinks resolved if it can
+/// be resolved, and unchanged if it cannot.
+// smoelius: Coverage files' paths and specified paths are both passed through this function, so a
+// file reached through a symlinked directory, or lying outside `root` (e.g., when --manifest-path
+// names a workspace elsewhere), has one name regardless of how it is written.
+pub(crate) fn resolve_path(root: &Path, path: &str) -> String {
+    let path_buf = root.join(path);
+    let canonical = path_buf.canonicalize().ok();
+    canonical
+        .as_deref()
+        .and_then(|canonical| canonical.strip_prefix(root).ok())
+        .or_else(|| {
+            path_buf
+                .strip_prefix(root)
+                .ok()
+                .filter(|path| !path.components().any(|c| c == Component::ParentDir))
+        })
+        .or(canonical.as_deref())
+        .and_then(Path::to_str)
+        .map_or_else(|| path.to_owned(), ToOwned::to_owned)
+}
+
+/// Returns a `cargo metadata` command for the workspace, i.e., the one containing the manifest
+/// passed to --manifest-path, if any.
+pub(crate) fn metadata_command() -> MetadataCommand {
+    let mut command = MetadataCommand::new();
+    command.no_deps();
+    if let Some(manifest_path) = &opts::get().manifest_path {
+        command.manifest_path(manifest_path);
+    }
+    command
+}
+
+/// Returns the package of `metadata` whose directory most closely contains `path`, an absolute
+/// path, if any.
+pub(crate) fn owning_package<'a>(metadata: &'a Metadata, path: &Path) -> Option<&'a Package> {
+    metadata
+        .packages
+        .iter()
+        .filter(|package| {
+            package
+                .manifest_path
+                .parent()
+                .is_some_and(|dir| path.starts_with(dir))
+        })
+        .max_by_key(|package| package.manifest_path.as_str().len())
+}
+
+/// Renders `command` as a line that can be pasted into a POSIX shell, including the directory in
+/// which it runs and any environment variables set on it.
+// smoelius: The program is shown as resolved, e.g., from the `CARGO` environment variable, so
+// `CARGO` itself need not be shown.
+pub(crate) fn shell_command(command: &Command) -> Result<String> {
+    let dir = match command.get_current_dir() {
+        Some(dir) => dir.to_path_buf(),
+        None => current_dir()?,
+    };
+    let mut line = format!("cd {} &&", escape(dir.as_os_str()));
+    for (key, value) in command.get_envs() {
+        if let Some(value) = value {
+            write!(line, " {}={}", key.to_string_lossy(), escape(value))?;
+        }
+    }
+    write!(line, " {}", escape(command.get_program()))?;
+    for arg in command.get_args() {
+        write!(line, " {}", escape(arg))?;
+    }
+    Ok(line)
+}
+
+fn escape(s: &std::ffi::OsStr) -> String {
+    shell_escape::unix::escape(s.to_string_lossy()).into_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{hash_path_contents, N_HASHED};
+    use crate::HashAlgo;
+    use std::{fs::write, sync::atomic::Ordering};
+    use tempfile::tempdir;
+
+    #[test]
+    fn cache() {
+        let tempdir = tempdir().unwrap();
+        let path = tempdir.path().join("file.txt");
+        write(&path, "x").unwrap();
+
+        let before = N_HASHED.load(Ordering::SeqCst);
+        let digest = hash_path_contents(&path, HashAlgo::Sha256).unwrap();
+        assert_eq!(before + 1, N_HASHED.load(Ordering::SeqCst));
+        assert_eq!(digest, hash_path_contents(&path, HashAlgo::Sha256).unwrap());
+        assert_eq!(before + 1, N_HASHED.load(Ordering::SeqCst));
+
+        // smoelius: Changing the size invalidates the cache entry even if the modification time
+        // is unchanged, e.g., because of a coarse timestamp granularity.
+        write(&path, "xy").unwrap();
+        assert_ne!(digest, hash_path_contents(&path, HashAlgo::Sha256).unwrap());
+        assert_eq!(before + 2, N_HASHED.load(Ordering::SeqCst));
+    }
 }
@@ -0,0 +1,116 @@
+use crate::{
+    merge_path_line_maps, opts, parse_line_specifications, read_diff, run_tests, set_ctrlc_handler,
+    PathColumnMap, PathFunctionMap, CTRLC,
+};
+use anyhow::{bail, ensure, Result};
+use notify::{recommended_watcher, Event, EventKind, RecursiveMode, Watcher};
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::Ordering,
+        mpsc::{channel, Receiver, RecvTimeoutError},
+    },
+    time::Duration,
+};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+const DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// Runs the selected tests, and then re-runs them each time a file named in a line specification
+/// or changed by the diff changes. Returns when ctrl-c is pressed.
+pub(crate) fn watch() -> Result<()> {
+    ensure!(
+        !opts::get().diff || opts::get().diff_file.is_some() || opts::get().diff_base.is_some(),
+        "--watch cannot be used with a diff read from standard input; use --diff-file or \
+         --diff-base"
+    );
+
+    set_ctrlc_handler()?;
+
+    let (tx, rx) = channel();
+    let mut watcher = recommended_watcher(tx)?;
+    let mut paths = BTreeSet::new();
+    let mut dirs = BTreeSet::new();
+
+    loop {
+        // smoelius: The diff is re-read before each run, so files it newly changes are watched
+        // too. Files are never unwatched: one that leaves the diff could re-enter it.
+        paths.extend(watched_paths()?);
+
+        // smoelius: Watch the paths' parent directories rather than the paths themselves. Many
+        // editors save a file by replacing it, which would end a watch on the file itself.
+        for dir in paths.iter().filter_map(|path| path.parent()) {
+            if dirs.insert(dir.to_path_buf()) {
+                watcher.watch(dir, RecursiveMode::NonRecursive)?;
+            }
+        }
+
+        if let Err(error) = run_tests() {
+            if CTRLC.load(Ordering::SeqCst) {
+                return Ok(());
+            }
+            eprintln!("Error: {error:?}");
+        }
+
+        eprintln!("Waiting for changes; press ctrl-c to exit");
+
+        if !wait_for_change(&rx, &paths)? {
+            return Ok(());
+        }
+    }
+}
+
+/// Returns the canonical paths of the files named in line specifications, of the files the diff
+/// changes if --diff is used, and of the diff file if --diff-file is used.
+fn watched_paths() -> Result<BTreeSet<PathBuf>> {
+    let mut path_function_map = PathFunctionMap::new();
+    let (mut path_line_map, line_dash_used) =
+        parse_line_specifications(&mut path_function_map, &mut PathColumnMap::new())?;
+    ensure!(!line_dash_used, "--watch cannot be used with `--line -`");
+
+    if opts::get().diff {
+        merge_path_line_maps(&mut path_line_map, read_diff()?);
+    }
+
+    // smoelius: Nonexistent paths are warned about by `run_tests`.
+    Ok(path_line_map
+        .keys()
+        .chain(path_function_map.keys())
+        .map(Path::new)
+        .chain(opts::get().diff_file.as_deref())
+        .filter_map(|path| path.canonicalize().ok())
+        .collect())
+}
+
+/// Waits until one of `paths` changes and no further events arrive for [`DEBOUNCE`]. Returns false
+/// if ctrl-c is pressed first.
+fn wait_for_change(
+    rx: &Receiver<notify::Result<Event>>,
+    paths: &BTreeSet<PathBuf>,
+) -> Result<bool> {
+    let mut changed = false;
+    loop {
+        match rx.recv_timeout(if changed { DEBOUNCE } else { POLL_INTERVAL }) {
+            Ok(result) => {
+                let event = result?;
+                // smoelius: Building and running tests reads the watched files. Ignore such
+                // accesses.
+                if !matches!(event.kind, EventKind::Access(_))
+                    && event.paths.iter().any(|path| paths.contains(path))
+                {
+                    changed = true;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                if CTRLC.load(Ordering::SeqCst) {
+                    return Ok(false);
+                }
+                if changed {
+                    return Ok(true);
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => bail!("file watcher disconnected"),
+        }
+    }
+}
@@ -0,0 +1,776 @@
+use assert_cmd::cargo::CommandCargoExt;
+use std::{
+    fs::{
+        metadata, read, read_to_string, remove_file, set_permissions, write, OpenOptions,
+        Permissions,
+    },
+    io::Write,
+    os::unix::fs::PermissionsExt,
+    path::Path,
+    process::Command,
+    time::SystemTime,
+};
+
+mod common;
+use common::{output_with_stdin, shown_tests, Fixture};
+
+const LCOV: &str = "line-test.db/packages/my-package/lib/tests::it_works.lcov";
+
+#[test]
+fn fresh_build() {
+    let fixture = Fixture::built("my-package");
+    assert!(fixture.path().join(LCOV).try_exists().unwrap());
+    assert!(fixture
+        .path()
+        .join("line-test.db/digests.json")
+        .try_exists()
+        .unwrap());
+}
+
+#[test]
+fn failed_rebuild_restores_existing_db() {
+    let fixture = Fixture::built("my-package");
+    let lcov = read_to_string(fixture.path().join(LCOV)).unwrap();
+
+    write(fixture.path().join("src/lib.rs"), "syntax error").unwrap();
+
+    let output = fixture.command().arg("--build").output().unwrap();
+    assert!(!output.status.success());
+    assert_eq!(lcov, read_to_string(fixture.path().join(LCOV)).unwrap());
+}
+
+#[test]
+fn missing_only_builds_missing_coverage() {
+    let fixture = Fixture::built("my-package");
+
+    remove_file(fixture.path().join(LCOV)).unwrap();
+
+    let status = fixture
+        .command()
+        .args(["--build", "--missing-only"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(fixture.path().join(LCOV).try_exists().unwrap());
+}
+
+#[test]
+fn missing_only_without_db() {
+    let fixture = Fixture::copy("my-package");
+
+    let output = fixture
+        .command()
+        .args(["--build", "--missing-only"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(
+        stderr.contains("--missing-only requires an existing one"),
+        "{stderr}"
+    );
+    assert!(!fixture.path().join("line-test.db").try_exists().unwrap());
+
+    // smoelius: The message names the database actually looked for.
+    let db = fixture.path().join("custom.db");
+    let output = fixture
+        .command()
+        .args(["--build", "--missing-only"])
+        .env("LINE_TEST_DB", &db)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(
+        stderr.contains(&format!("{} does not exist", db.display())),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn invalid_combinations() {
+    let fixture = Fixture::new();
+
+    for args in [
+        &["--missing-only"][..],
+        &["--build", "--no-run"],
+        &["--build", "--batch", "--clean"],
+    ] {
+        let output = fixture.command().args(args).output().unwrap();
+        assert_eq!(Some(2), output.status.code(), "{args:?}");
+    }
+}
+
+#[test]
+fn build_writes_lcov_per_test() {
+    let fixture = Fixture::copy("features");
+
+    let output = fixture
+        .command()
+        .args([
+            "--build",
+            "--batch",
+            "--features",
+            "extra",
+            "--show-commands",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    // smoelius: With --batch, the crate's test binary is built once and reused for both tests.
+    let stdout = std::str::from_utf8(&output.stdout).unwrap();
+    assert_eq!(1, stdout.matches(" test --no-run ").count(), "{stdout}");
+    assert_eq!(2, stdout.matches(" llvm-cov report ").count(), "{stdout}");
+
+    let dir = fixture.path().join("line-test.db/packages/features/lib");
+    let add_works = read_to_string(dir.join("tests::add_works.lcov")).unwrap();
+    let sub_works = read_to_string(dir.join("tests::sub_works.lcov")).unwrap();
+
+    // smoelius: Line 2 is in `add`, and line 7 is in `sub`.
+    assert!(add_works.contains("\nDA:2,1\n"), "{add_works}");
+    assert!(!add_works.contains("\nDA:7,1\n"), "{add_works}");
+    assert!(sub_works.contains("\nDA:7,1\n"), "{sub_works}");
+    assert!(!sub_works.contains("\nDA:2,1\n"), "{sub_works}");
+}
+
+#[test]
+fn verbose_lists_removed_profraw_files() {
+    let fixture = Fixture::copy("features");
+
+    let output = fixture
+        .command()
+        .args(["--build", "--features", "extra", "--verbose"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    // smoelius: The first test's profraw file is removed before the second test is run.
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(
+        stderr
+            .lines()
+            .any(|line| line.starts_with("Removed ") && line.ends_with(".profraw")),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("profraw files remain"), "{stderr}");
+}
+
+#[test]
+fn build_script_only() {
+    let mut command = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    command.args(["line-test", "--build", "--verbose"]);
+    command.current_dir("fixtures/build-script-only");
+    let output = command.output().unwrap();
+    assert!(output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(
+        stderr.contains("skipping package `build-script-only` target `build_script_only`"),
+        "{stderr}"
+    );
+    // smoelius: `cargo test` prints "Running" when it runs a test binary, e.g., to list its tests.
+    assert!(!stderr.contains("Running"), "{stderr}");
+}
+
+#[test]
+fn clean() {
+    let fixture = Fixture::copy("my-package");
+
+    for (args, no_clean) in [(&[][..], true), (&["--clean"], false)] {
+        let output = fixture
+            .command()
+            .args(["--build", "--show-commands"])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = std::str::from_utf8(&output.stdout).unwrap();
+        assert!(stdout.contains(" llvm-cov "), "{stdout}");
+        assert_eq!(no_clean, stdout.contains(" --no-clean "), "{stdout}");
+        assert!(!stdout.contains(" llvm-cov report "), "{stdout}");
+        assert!(fixture
+            .path()
+            .join("line-test.db/packages/my-package/lib/tests::it_works.lcov")
+            .try_exists()
+            .unwrap());
+    }
+}
+
+// smoelius: Cargo rejects workspaces in which two packages have the same name, so packages are
+// keyed by name. But two packages' crates can have the same name, e.g., via `[lib] name`. Each
+// package's coverage is stored beneath its own directory, so neither overwrites the other's.
+#[test]
+fn packages_with_same_crate_name_are_tracked_separately() {
+    let fixture = Fixture::built("crate-name-collision");
+
+    for package in ["first", "second"] {
+        assert!(fixture
+            .path()
+            .join("line-test.db/packages")
+            .join(package)
+            .join("lib/tests::it_works.lcov")
+            .try_exists()
+            .unwrap());
+    }
+
+    for (path, package) in [
+        ("first/src/lib.rs:2", "first"),
+        ("second/src/lib.rs:2", "second"),
+    ] {
+        let output = fixture
+            .command()
+            .args(["--no-run", "--line", path])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(vec!["tests::it_works"], shown_tests(&output.stdout));
+        let stdout = std::str::from_utf8(&output.stdout).unwrap();
+        assert_eq!(1, stdout.lines().count(), "{stdout}");
+        assert!(
+            stdout.contains(&format!("--package {package} ")),
+            "{stdout}"
+        );
+    }
+}
+
+#[test]
+fn env() {
+    let fixture = Fixture::copy("env");
+
+    let status = fixture
+        .command()
+        .args(["--build", "--env", "GREETING=hello"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let output = fixture
+        .command()
+        .args([
+            "--deny-warnings",
+            "--line",
+            "src/lib.rs:2",
+            "--env",
+            "GREETING=hello",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let output = fixture
+        .command()
+        .args(["--deny-warnings", "--line", "src/lib.rs:2"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(stderr.contains("test command failed"), "{stderr}");
+
+    let output = fixture
+        .command()
+        .args(["--line", "src/lib.rs:2", "--env", "GREETING"])
+        .output()
+        .unwrap();
+    assert_eq!(Some(2), output.status.code());
+}
+
+#[test]
+fn feature_gated_test_is_enumerated_only_when_enabled() {
+    let lcov = "line-test.db/packages/features/lib/tests::sub_works.lcov";
+
+    let fixture = Fixture::built("features");
+    assert!(!fixture.path().join(lcov).try_exists().unwrap());
+
+    for args in [&["--features", "extra"][..], &["--all-features"]] {
+        let status = fixture
+            .command()
+            .arg("--build")
+            .args(args)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        assert!(fixture.path().join(lcov).try_exists().unwrap());
+
+        let output = fixture
+            .command()
+            .args(["--line", "src/lib.rs:7", "--no-run"])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = std::str::from_utf8(&output.stdout).unwrap();
+        assert!(stdout.contains("--features") || stdout.contains("--all-features"));
+        assert!(stdout.contains("tests::sub_works"), "{stdout}");
+        assert!(!stdout.contains("tests::add_works"), "{stdout}");
+    }
+}
+
+#[test]
+fn custom_profile() {
+    let fixture = Fixture::copy("profile");
+
+    let status = fixture
+        .command()
+        .args(["--build", "--profile", "test-fast"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(fixture
+        .path()
+        .join("target/llvm-cov-target/test-fast")
+        .try_exists()
+        .unwrap());
+
+    let output = fixture
+        .command()
+        .args(["--line", "src/lib.rs:2", "--profile", "test-fast"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:#?}");
+
+    let output = fixture
+        .command()
+        .args([
+            "--no-run",
+            "--line",
+            "src/lib.rs:2",
+            "--profile",
+            "test-fast",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:#?}");
+    assert_eq!(vec!["tests::it_works"], shown_tests(&output.stdout));
+    assert!(std::str::from_utf8(&output.stdout)
+        .unwrap()
+        .contains(" --profile test-fast "));
+}
+
+// smoelius: The fixture's coverage is deterministic, so no instability should be reported. The
+// `unstable_coverage` unit test in src/db/build/repeat.rs shows how a flaky test is flagged.
+#[test]
+fn deterministic_coverage_is_stable() {
+    let fixture = Fixture::copy("my-package");
+
+    let output = fixture
+        .command()
+        .args(["--build", "--repeat", "2"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:#?}");
+    assert!(!std::str::from_utf8(&output.stderr)
+        .unwrap()
+        .contains("unstable"));
+
+    let lcov = read_to_string(fixture.path().join(LCOV)).unwrap();
+    assert!(lcov.lines().any(|line| line == "SF:src/lib.rs"), "{lcov}");
+}
+
+#[test]
+fn repeat_requires_build() {
+    let fixture = Fixture::copy("my-package");
+
+    let output = fixture.command().args(["--repeat", "2"]).output().unwrap();
+    assert!(!output.status.success());
+}
+
+#[test]
+fn repeat_zero_is_rejected_before_building() {
+    let fixture = Fixture::copy("my-package");
+
+    let output = fixture
+        .command()
+        .args(["--build", "--repeat", "0"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(
+        stderr.contains("invalid value '0' for '--repeat <N>'"),
+        "{stderr}"
+    );
+    assert!(!fixture.path().join("line-test.db").try_exists().unwrap());
+}
+
+const RUNNER: &str = "#!/bin/sh
+echo \"$1\" >> \"$(dirname \"$0\")/runner.log\"
+exec \"$@\"
+";
+
+#[test]
+fn tests_run_through_runner() {
+    let fixture = Fixture::copy("my-package");
+    let runner = fixture.path().join("runner.sh");
+    write(&runner, RUNNER).unwrap();
+    set_permissions(&runner, Permissions::from_mode(0o755)).unwrap();
+    let runner_cmd = runner.to_str().unwrap();
+
+    let status = fixture
+        .command()
+        .args(["--build", "--runner-cmd", runner_cmd])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(fixture
+        .path()
+        .join("line-test.db/packages/my-package/lib/tests::it_works.lcov")
+        .try_exists()
+        .unwrap());
+    let n_built = read_to_string(fixture.path().join("runner.log"))
+        .unwrap()
+        .lines()
+        .count();
+    assert!(n_built >= 1);
+
+    let output = fixture
+        .command()
+        .args([
+            "--no-run",
+            "--line",
+            "src/lib.rs:2",
+            "--runner-cmd",
+            runner_cmd,
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:#?}");
+    assert_eq!(vec!["tests::it_works"], shown_tests(&output.stdout));
+    let stdout = std::str::from_utf8(&output.stdout).unwrap();
+    assert!(stdout.contains("_RUNNER="), "{stdout}");
+
+    let output = fixture
+        .command()
+        .args(["--line", "src/lib.rs:2", "--runner-cmd", runner_cmd])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:#?}");
+    let n_run = read_to_string(fixture.path().join("runner.log"))
+        .unwrap()
+        .lines()
+        .count();
+    assert!(n_run > n_built);
+}
+
+const LCOV_A: &str = "line-test.db/packages/a/lib/tests::add_works.lcov";
+const LCOV_B: &str = "line-test.db/packages/b/lib/tests::add_works.lcov";
+
+#[test]
+fn only_affecting_reruns_covering_tests() {
+    let fixture = Fixture::built("workspace");
+
+    let modified = |path: &str| -> SystemTime {
+        metadata(fixture.path().join(path))
+            .unwrap()
+            .modified()
+            .unwrap()
+    };
+    let (before_a, before_b) = (modified(LCOV_A), modified(LCOV_B));
+
+    let mut file = OpenOptions::new()
+        .append(true)
+        .open(fixture.path().join("a/src/lib.rs"))
+        .unwrap();
+    writeln!(file, "// changed").unwrap();
+
+    let output = fixture
+        .command()
+        .args([
+            "--build",
+            "--only-affecting",
+            "a/src/lib.rs",
+            "--only-affecting",
+            "a/src/missing.rs",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:#?}");
+    assert!(std::str::from_utf8(&output.stderr)
+        .unwrap()
+        .contains("a/src/missing.rs is not covered by any test in line-test.db"));
+
+    assert_ne!(before_a, modified(LCOV_A));
+    assert_eq!(before_b, modified(LCOV_B));
+
+    // smoelius: The digests were updated, so there is nothing left to refresh.
+    let output = fixture
+        .command()
+        .args(["--refresh", "--no-run"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:#?}");
+    assert!(output.stdout.is_empty(), "{output:#?}");
+}
+
+#[test]
+fn missing_cargo() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n2\n")
+        .write_lcov("package", "lib", "tests::a", &[("src/lib.rs", &[1])])
+        .write_digests(&["src/lib.rs"]);
+
+    let output = fixture
+        .command()
+        .env("CARGO", fixture.path().join("nonexistent-cargo"))
+        .args(["--line", "src/lib.rs:1"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(stderr.contains("failed to spawn command"), "{stderr}");
+    assert!(!stderr.contains("test command failed"), "{stderr}");
+}
+
+// smoelius: `script` runs the command under a pseudo-terminal, so that stderr is a terminal.
+fn build_under_pty(fixture: &Fixture, args: &[&str]) -> String {
+    let command = fixture.command();
+    let mut shell_command = format!(
+        "'{}' line-test --build",
+        Path::new(command.get_program()).display()
+    );
+    for arg in args {
+        shell_command.push(' ');
+        shell_command.push_str(arg);
+    }
+    let output = Command::new("script")
+        .args([
+            "--quiet",
+            "--return",
+            "--command",
+            &shell_command,
+            "/dev/null",
+        ])
+        .current_dir(fixture.path())
+        .env_remove("TERM")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn no_progress() {
+    let fixture = Fixture::copy("my-package");
+
+    let transcript = build_under_pty(&fixture, &[]);
+    assert!(transcript.contains("(0%) package:"), "{transcript}");
+
+    let transcript = build_under_pty(&fixture, &["--no-progress"]);
+    assert!(!transcript.contains("%) package:"), "{transcript}");
+}
+
+// smoelius: Line 6 of the fixture's src/lib.rs is in `mul`, which only the ignored test
+// `tests::mul_works` calls.
+
+#[test]
+fn ignored_test_coverage_is_recorded() {
+    let fixture = Fixture::copy("ignored");
+
+    let status = fixture
+        .command()
+        .args(["--build", "--include-ignored"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--line", "src/lib.rs:6"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:#?}");
+    assert_eq!(vec!["tests::mul_works"], shown_tests(&output.stdout));
+}
+
+#[test]
+fn ignored_test_coverage_is_not_recorded_by_default() {
+    let fixture = Fixture::built("ignored");
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--line", "src/lib.rs:6"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:#?}");
+    assert!(shown_tests(&output.stdout).is_empty());
+}
+
+#[test]
+fn estimate_counts_tests() {
+    let fixture = Fixture::copy("ignored");
+
+    let output = fixture
+        .command()
+        .args(["--build", "--estimate"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:#?}");
+    let stdout = std::str::from_utf8(&output.stdout).unwrap();
+    assert!(stdout.starts_with("2 tests would be run\n"), "{stdout}");
+    assert!(
+        stdout.contains("No previous build time is recorded"),
+        "{stdout}"
+    );
+    assert!(!fixture.path().join("line-test.db").try_exists().unwrap());
+
+    let status = fixture.command().arg("--build").status().unwrap();
+    assert!(status.success());
+
+    let output = fixture
+        .command()
+        .args(["--build", "--estimate"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:#?}");
+    let stdout = std::str::from_utf8(&output.stdout).unwrap();
+    assert!(stdout.starts_with("2 tests would be run\n"), "{stdout}");
+    assert!(
+        stdout.contains("Estimated build time: ") && stdout.contains("ran 2 tests in "),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn estimate_requires_build() {
+    let fixture = Fixture::copy("ignored");
+
+    let output = fixture.command().arg("--estimate").output().unwrap();
+    assert!(!output.status.success());
+}
+
+const A_ADD_DIFF: &str = "\
+--- a/a/src/lib.rs
++++ b/a/src/lib.rs
+@@ -1,3 +1,3 @@
+ pub fn add(left: u64, right: u64) -> u64 {
+-    left + right
++    right + left
+ }
+";
+
+#[test]
+fn only_changed_package_is_rebuilt() {
+    let fixture = Fixture::built("workspace");
+
+    let modified = |lcov: &str| {
+        metadata(fixture.path().join(lcov))
+            .unwrap()
+            .modified()
+            .unwrap()
+    };
+
+    let modified_a = modified(LCOV_A);
+    let modified_b = modified(LCOV_B);
+
+    let mut command = fixture.command();
+    command.args(["--build", "--packages-from-diff"]);
+    let output = output_with_stdin(command, A_ADD_DIFF);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    assert_ne!(modified_a, modified(LCOV_A));
+    assert_eq!(modified_b, modified(LCOV_B));
+}
+
+#[test]
+fn failed_package_rebuild_restores_coverage() {
+    let fixture = Fixture::built("workspace");
+
+    let lcov_a = read_to_string(fixture.path().join(LCOV_A)).unwrap();
+    let n_entries = || {
+        fixture
+            .path()
+            .join("line-test.db")
+            .read_dir()
+            .unwrap()
+            .count()
+    };
+    let n_entries_before = n_entries();
+
+    // smoelius: A runner that always fails makes listing package `a`'s tests fail, after its
+    // coverage has been moved aside.
+    let mut command = fixture.command();
+    command.args(["--build", "--packages-from-diff", "--runner-cmd", "false"]);
+    let output = output_with_stdin(command, A_ADD_DIFF);
+    assert!(!output.status.success());
+
+    assert_eq!(lcov_a, read_to_string(fixture.path().join(LCOV_A)).unwrap());
+    assert_eq!(n_entries_before, n_entries());
+}
+
+#[test]
+fn blake3_round_trip() {
+    let fixture = Fixture::copy("my-package");
+
+    let status = fixture
+        .command()
+        .args(["--build", "--hash-algo", "blake3"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let hash_algo = read_to_string(fixture.path().join("line-test.db/hash-algo.txt")).unwrap();
+    assert_eq!("blake3\n", hash_algo);
+
+    let json = read_to_string(fixture.path().join("line-test.db/digests.json")).unwrap();
+    let value = serde_json::from_str::<serde_json::Value>(&json).unwrap();
+    let bytes = read(fixture.path().join("src/lib.rs")).unwrap();
+    assert_eq!(
+        blake3::hash(&bytes).to_hex().as_str(),
+        value["src/lib.rs"].as_str().unwrap()
+    );
+
+    // smoelius: Nothing has changed, so refreshing should not select any tests.
+    let output = fixture
+        .command()
+        .args(["--refresh", "--no-run"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(shown_tests(&output.stdout).is_empty());
+
+    let output = fixture
+        .command()
+        .args(["--refresh", "--hash-algo", "sha256"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(
+        stderr.contains("rebuild it with `--build --hash-algo sha256`"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn db_readme() {
+    let readme = "line-test.db/README.txt";
+
+    let fixture = Fixture::built("my-package");
+    let contents = read_to_string(fixture.path().join(readme)).unwrap();
+    assert!(contents.contains("cargo-line-test"), "{contents}");
+
+    let status = fixture
+        .command()
+        .args(["--build", "--no-db-readme"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(!fixture.path().join(readme).try_exists().unwrap());
+
+    write(
+        fixture.path().join(".line-test.toml"),
+        "db_readme = \"See the team wiki.\\n\"\n",
+    )
+    .unwrap();
+
+    let status = fixture.command().arg("--build").status().unwrap();
+    assert!(status.success());
+    assert_eq!(
+        "See the team wiki.\n",
+        read_to_string(fixture.path().join(readme)).unwrap()
+    );
+}
@@ -0,0 +1,158 @@
+use std::{
+    fs::{read_to_string, write},
+    path::Path,
+};
+
+mod common;
+use common::{shown_tests, Fixture};
+
+#[test]
+fn generate_completions() {
+    let fixture = Fixture::new();
+    for shell in ["bash", "elvish", "fish", "powershell", "zsh"] {
+        let output = fixture
+            .command()
+            .args(["--generate-completions", shell])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{shell}");
+        let stdout = std::str::from_utf8(&output.stdout).unwrap();
+        assert!(stdout.contains("line-test"), "{shell}: {stdout}");
+    }
+}
+
+#[test]
+fn config_deny_warnings() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n2\n")
+        .write_lcov("package", "lib", "tests::a", &[("src/lib.rs", &[1])])
+        .write_digests(&["src/lib.rs"]);
+
+    // smoelius: Line 2 is not covered by any test, which is a warning.
+    let output = fixture
+        .command()
+        .args(["--no-run", "--line", "src/lib.rs:2"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    write(
+        fixture.path().join(".line-test.toml"),
+        "deny_warnings = true\n",
+    )
+    .unwrap();
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--line", "src/lib.rs:2"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(stderr.starts_with("Error: "), "{stderr}");
+}
+
+#[test]
+fn config_unknown_key() {
+    let fixture = Fixture::new();
+    write(
+        fixture.path().join(".line-test.toml"),
+        "no_such_option = 1\n",
+    )
+    .unwrap();
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--line", "src/lib.rs:1"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(
+        stderr.contains("failed to parse .line-test.toml"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn manifest_path_anchors_build_and_selection() {
+    let fixture = Fixture::copy("my-package");
+    let manifest_path = fixture.path().join("Cargo.toml");
+    let elsewhere = tempfile::tempdir().unwrap();
+
+    let command = |dir: &Path| {
+        let mut command = fixture.command();
+        command.current_dir(dir);
+        command.arg("--manifest-path").arg(&manifest_path);
+        command
+    };
+
+    let status = command(elsewhere.path()).arg("--build").status().unwrap();
+    assert!(status.success());
+    assert!(fixture
+        .path()
+        .join("line-test.db/packages/my-package/lib/tests::it_works.lcov")
+        .try_exists()
+        .unwrap());
+    assert!(!elsewhere.path().join("line-test.db").try_exists().unwrap());
+
+    // smoelius: Paths passed to options, including those in line specifications, remain relative to
+    // the current directory.
+    let line = format!("{}:2", fixture.path().join("src/lib.rs").display());
+    let output = command(elsewhere.path())
+        .args(["--no-run", "--line", &line, "--output", "tests.txt"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:#?}");
+    let contents = read_to_string(elsewhere.path().join("tests.txt")).unwrap();
+    assert_eq!(vec!["tests::it_works"], shown_tests(contents.as_bytes()));
+    assert!(!fixture.path().join("tests.txt").try_exists().unwrap());
+
+    let workspace = fixture.path().file_name().unwrap().to_str().unwrap();
+    let line = format!("../{workspace}/src/lib.rs:2");
+    let output = command(elsewhere.path())
+        .args(["--no-run", "--line", &line])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:#?}");
+    assert_eq!(vec!["tests::it_works"], shown_tests(&output.stdout));
+}
+
+#[test]
+fn manifest_path_anchors_config() {
+    let fixture = Fixture::copy("my-package");
+    fixture.write_source(".line-test.toml", "db_path = \"other.db\"\n");
+    let elsewhere = tempfile::tempdir().unwrap();
+
+    let status = fixture
+        .command()
+        .current_dir(elsewhere.path())
+        .arg("--manifest-path")
+        .arg(fixture.path().join("Cargo.toml"))
+        .arg("--build")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(fixture
+        .path()
+        .join("other.db/packages/my-package/lib/tests::it_works.lcov")
+        .try_exists()
+        .unwrap());
+    assert!(!elsewhere.path().join("other.db").try_exists().unwrap());
+}
+
+#[test]
+fn manifest_path_must_name_cargo_toml() {
+    let fixture = Fixture::copy("my-package");
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--manifest-path", "src/lib.rs"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(std::str::from_utf8(&output.stderr)
+        .unwrap()
+        .contains("--manifest-path must be a path to a Cargo.toml file"));
+}
@@ -0,0 +1,200 @@
+#![allow(dead_code)]
+
+use assert_cmd::cargo::CommandCargoExt;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs::{copy, create_dir_all, read, read_dir, write},
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+};
+use tempfile::{tempdir, TempDir};
+
+// smoelius: A `Fixture` is a temporary directory in which a line-test.db can be written by hand.
+// Writing the db by hand avoids running `cargo llvm-cov`, and gives tests precise control over
+// which lines each test covers.
+pub struct Fixture {
+    tempdir: TempDir,
+    root: PathBuf,
+}
+
+impl Fixture {
+    pub fn new() -> Self {
+        let tempdir = tempdir().unwrap();
+        let root = tempdir.path().canonicalize().unwrap();
+        Self { tempdir, root }
+    }
+
+    /// Copies the named directory under `fixtures` into a new temporary directory.
+    pub fn copy(name: &str) -> Self {
+        let fixture = Self::new();
+        copy_dir(
+            &Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("fixtures")
+                .join(name),
+            fixture.path(),
+        );
+        fixture
+    }
+
+    /// Like [`Fixture::copy`], but also builds the copy's line-test.db.
+    pub fn built(name: &str) -> Self {
+        let fixture = Self::copy(name);
+        let status = fixture.command().arg("--build").status().unwrap();
+        assert!(status.success());
+        fixture
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn write_source(&self, path: &str, contents: &str) -> &Self {
+        let path_buf = self.root.join(path);
+        create_dir_all(path_buf.parent().unwrap()).unwrap();
+        write(path_buf, contents).unwrap();
+        self
+    }
+
+    /// Writes an lcov file for `test` in which each path's lines are covered.
+    pub fn write_lcov(
+        &self,
+        package: &str,
+        krate: &str,
+        test: &str,
+        coverage: &[(&str, &[u32])],
+    ) -> &Self {
+        self.write_lcov_in("line-test.db", package, krate, test, coverage)
+    }
+
+    /// Like [`Fixture::write_lcov`], but writes to the database directory `db`.
+    pub fn write_lcov_in(
+        &self,
+        db: &str,
+        package: &str,
+        krate: &str,
+        test: &str,
+        coverage: &[(&str, &[u32])],
+    ) -> &Self {
+        let mut lcov = String::new();
+        for &(path, lines) in coverage {
+            writeln!(lcov, "SF:{}", self.root.join(path).display()).unwrap();
+            for line in lines {
+                writeln!(lcov, "DA:{line},1").unwrap();
+            }
+            writeln!(lcov, "end_of_record").unwrap();
+        }
+        self.write_lcov_contents(db, package, krate, test, &lcov)
+    }
+
+    /// Writes `contents` as the lcov file for `test` in the database directory `db`. Occurrences
+    /// of `$ROOT` in `contents` are replaced with the fixture's path.
+    pub fn write_lcov_contents(
+        &self,
+        db: &str,
+        package: &str,
+        krate: &str,
+        test: &str,
+        contents: &str,
+    ) -> &Self {
+        let lcov = contents.replace("$ROOT", &self.root.to_string_lossy());
+        let dir = self
+            .root
+            .join(db)
+            .join("packages")
+            .join(package)
+            .join(krate);
+        create_dir_all(&dir).unwrap();
+        write(dir.join(format!("{test}.lcov")), lcov).unwrap();
+        self
+    }
+
+    /// Writes line-test.db/digests.json using the current contents of `paths`.
+    pub fn write_digests(&self, paths: &[&str]) -> &Self {
+        self.write_digests_in("line-test.db", paths)
+    }
+
+    /// Like [`Fixture::write_digests`], but writes to the database directory `db`.
+    pub fn write_digests_in(&self, db: &str, paths: &[&str]) -> &Self {
+        let mut path_hex_map = BTreeMap::new();
+        for &path in paths {
+            let bytes = read(self.root.join(path)).unwrap();
+            let digest = Sha256::digest(bytes);
+            path_hex_map.insert(path.to_owned(), hex::encode(digest));
+        }
+        let json = serde_json::to_string_pretty(&path_hex_map).unwrap();
+        create_dir_all(self.root.join(db)).unwrap();
+        write(self.root.join(db).join("digests.json"), json).unwrap();
+        self
+    }
+
+    /// Returns a `cargo line-test` command whose current directory is the fixture's.
+    pub fn command(&self) -> Command {
+        let mut command = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        command.arg("line-test");
+        command.current_dir(&self.root);
+        command
+    }
+}
+
+fn copy_dir(from: &Path, to: &Path) {
+    create_dir_all(to).unwrap();
+    for result in read_dir(from).unwrap() {
+        let entry = result.unwrap();
+        let file_name = entry.file_name();
+        if file_name == "target" || file_name == "line-test.db" {
+            continue;
+        }
+        let path = entry.path();
+        if path.is_dir() {
+            copy_dir(&path, &to.join(file_name));
+        } else {
+            copy(&path, to.join(file_name)).unwrap();
+        }
+    }
+}
+
+/// Returns the tests whose commands appear in `stdout`, e.g., the output of `--no-run`.
+pub fn shown_tests(stdout: &[u8]) -> Vec<String> {
+    std::str::from_utf8(stdout)
+        .unwrap()
+        .lines()
+        .filter_map(|line| line.rsplit_once(" --exact "))
+        .map(|(_, test)| test.trim_matches('\'').to_owned())
+        .collect()
+}
+
+/// Runs `command` with `stdin` as its standard input and returns its output.
+pub fn output_with_stdin(mut command: Command, stdin: &str) -> Output {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+/// Runs `git` with `args` in `dir`, asserts that it succeeds, and returns its trimmed standard
+/// output.
+pub fn git(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .trim_end()
+        .to_owned()
+}
@@ -0,0 +1,719 @@
+use std::{
+    collections::BTreeMap,
+    fs::{metadata, read_dir, read_to_string, remove_file, rename, write},
+};
+
+mod common;
+use common::{shown_tests, Fixture};
+
+#[test]
+fn refresh_no_run_lists_affected_tests() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/a.rs", "1\n")
+        .write_source("src/b.rs", "1\n")
+        .write_lcov("package", "lib", "tests::a", &[("src/a.rs", &[1])])
+        .write_lcov("package", "lib", "tests::b", &[("src/b.rs", &[1])])
+        .write_digests(&["src/a.rs", "src/b.rs"]);
+
+    let digests = read_to_string(fixture.path().join("line-test.db/digests.json")).unwrap();
+
+    write(fixture.path().join("src/a.rs"), "1\n2\n").unwrap();
+
+    let output = fixture
+        .command()
+        .args(["--refresh", "--no-run"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(vec!["tests::a"], shown_tests(&output.stdout));
+
+    // smoelius: Neither the digests nor the coverage files were touched.
+    assert_eq!(
+        digests,
+        read_to_string(fixture.path().join("line-test.db/digests.json")).unwrap()
+    );
+    let lcovs = read_dir(fixture.path().join("line-test.db/packages/package/lib"))
+        .unwrap()
+        .count();
+    assert_eq!(2, lcovs);
+}
+
+#[test]
+fn refresh_warns_about_tests_covering_only_deleted_files() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/a.rs", "1\n")
+        .write_source("src/b.rs", "1\n")
+        .write_lcov(
+            "package",
+            "lib",
+            "tests::both",
+            &[("src/a.rs", &[1]), ("src/b.rs", &[1])],
+        )
+        .write_lcov("package", "lib", "tests::b_only", &[("src/b.rs", &[1])])
+        .write_digests(&["src/a.rs", "src/b.rs"]);
+
+    remove_file(fixture.path().join("src/b.rs")).unwrap();
+
+    let output = fixture
+        .command()
+        .args(["--refresh", "--no-run"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(vec!["tests::both"], shown_tests(&output.stdout));
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(
+        stderr.contains("test `tests::b_only` covered only deleted files"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn refresh_removes_digests_of_deleted_files() {
+    let fixture = Fixture::copy("my-package");
+    fixture.write_source("src/extra.rs", "pub fn one() -> u64 {\n    1\n}\n");
+    let lib_rs = read_to_string(fixture.path().join("src/lib.rs")).unwrap();
+    fixture.write_source(
+        "src/lib.rs",
+        &format!(
+            "mod extra;\n\n{}",
+            lib_rs.replace("add(2, 2)", "add(2, 2) + extra::one() - 1")
+        ),
+    );
+
+    let status = fixture.command().arg("--build").status().unwrap();
+    assert!(status.success());
+    assert!(digests(&fixture).contains("src/extra.rs"));
+
+    remove_file(fixture.path().join("src/extra.rs")).unwrap();
+    fixture.write_source("src/lib.rs", &lib_rs);
+
+    let status = fixture.command().arg("--refresh").status().unwrap();
+    assert!(status.success());
+    let digests = digests(&fixture);
+    assert!(!digests.contains("src/extra.rs"), "{digests}");
+    assert!(digests.contains("src/lib.rs"), "{digests}");
+}
+
+fn digests(fixture: &Fixture) -> String {
+    read_to_string(fixture.path().join("line-test.db/digests.json")).unwrap()
+}
+
+#[test]
+fn refresh_consults_index() {
+    let fixture = Fixture::built("my-package");
+
+    let index = read_to_string(fixture.path().join("line-test.db/index.json")).unwrap();
+    let value = serde_json::from_str::<serde_json::Value>(&index).unwrap();
+    assert_eq!(
+        serde_json::json!(["src/lib.rs"]),
+        value["my-package"]["lib"]["tests::it_works"]
+    );
+
+    // smoelius: Corrupt the coverage file so that refresh would fail were it parsed.
+    write(
+        fixture
+            .path()
+            .join("line-test.db/packages/my-package/lib/tests::it_works.lcov"),
+        "not lcov\n",
+    )
+    .unwrap();
+
+    let output = fixture
+        .command()
+        .args(["--refresh", "--no-run"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(shown_tests(&output.stdout).is_empty());
+
+    let lib_rs = read_to_string(fixture.path().join("src/lib.rs")).unwrap();
+    write(fixture.path().join("src/lib.rs"), format!("{lib_rs}\n")).unwrap();
+
+    let output = fixture
+        .command()
+        .args(["--refresh", "--no-run"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(vec!["tests::it_works"], shown_tests(&output.stdout));
+}
+
+#[test]
+fn refresh_updates_only_changed_digests() {
+    let fixture = Fixture::copy("my-package");
+    fixture.write_source("src/extra.rs", "pub fn one() -> u64 {\n    1\n}\n");
+    let lib_rs = read_to_string(fixture.path().join("src/lib.rs")).unwrap();
+    fixture.write_source(
+        "src/lib.rs",
+        &format!(
+            "mod extra;\n\n{}",
+            lib_rs.replace("add(2, 2)", "add(2, 2) + extra::one() - 1")
+        ),
+    );
+
+    let status = fixture.command().arg("--build").status().unwrap();
+    assert!(status.success());
+    let before = digest_map(&fixture);
+
+    fixture.write_source("src/extra.rs", "pub fn one() -> u64 {\n    2 - 1\n}\n");
+
+    let status = fixture.command().arg("--refresh").status().unwrap();
+    assert!(status.success());
+    let after = digest_map(&fixture);
+
+    assert_eq!(before["src/lib.rs"], after["src/lib.rs"]);
+    assert_ne!(before["src/extra.rs"], after["src/extra.rs"]);
+}
+
+fn digest_map(fixture: &Fixture) -> BTreeMap<String, String> {
+    serde_json::from_str(&digests(fixture)).unwrap()
+}
+
+const LCOV: &str = "line-test.db/packages/my-package/lib/tests::it_works.lcov";
+
+#[test]
+fn lcov_paths_are_relative() {
+    let fixture = Fixture::built("my-package");
+
+    let lcov = read_to_string(fixture.path().join(LCOV)).unwrap();
+    assert!(lcov.lines().any(|line| line == "SF:src/lib.rs"), "{lcov}");
+}
+
+#[test]
+fn moved_db_selects_tests() {
+    let fixture = Fixture::built("my-package");
+
+    let elsewhere = Fixture::new();
+    let moved = elsewhere.path().join("moved");
+    rename(fixture.path(), &moved).unwrap();
+
+    let output = elsewhere
+        .command()
+        .current_dir(&moved)
+        .args(["--no-run", "--line", "src/lib.rs:2"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:#?}");
+    assert_eq!(vec!["tests::it_works"], shown_tests(&output.stdout));
+}
+
+const DIGESTS: &str = "line-test.db/digests.json";
+
+#[test]
+fn missing_digests_is_explained() {
+    let fixture = Fixture::new();
+    fixture.write_source("src/lib.rs", "1\n2\n").write_lcov(
+        "package",
+        "lib",
+        "tests::a",
+        &[("src/lib.rs", &[1])],
+    );
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--line", "src/lib.rs:1"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(
+        stderr.contains(
+            "digests.json is missing; run with `--fix` to rebuild it from the existing coverage, \
+             or with `--build` to rebuild the database"
+        ),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn fix_rebuilds_digests() {
+    let fixture = Fixture::copy("my-package");
+    fixture.write_lcov(
+        "my-package",
+        "lib",
+        "tests::it_works",
+        &[("src/lib.rs", &[2])],
+    );
+
+    let status = fixture.command().arg("--fix").status().unwrap();
+    assert!(status.success());
+    assert!(fixture.path().join(DIGESTS).try_exists().unwrap());
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--line", "src/lib.rs:2"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(vec!["tests::it_works"], shown_tests(&output.stdout));
+}
+
+#[test]
+fn fix_without_db() {
+    let fixture = Fixture::new();
+
+    let output = fixture.command().arg("--fix").output().unwrap();
+    assert!(!output.status.success());
+    assert!(std::str::from_utf8(&output.stderr)
+        .unwrap()
+        .contains("does not exist; run with `--build` to build it"));
+}
+
+#[test]
+fn fix_recreates_digests_unlike_refresh_no_run() {
+    let fixture = Fixture::copy("my-package");
+    fixture
+        .write_lcov(
+            "my-package",
+            "lib",
+            "tests::it_works",
+            &[("src/lib.rs", &[2])],
+        )
+        .write_digests(&["src/lib.rs"]);
+    let digests = read_to_string(fixture.path().join(DIGESTS)).unwrap();
+    remove_file(fixture.path().join(DIGESTS)).unwrap();
+
+    // smoelius: `--refresh --no-run` writes nothing, so it does not recreate digests.json.
+    let output = fixture
+        .command()
+        .args(["--refresh", "--no-run"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(!fixture.path().join(DIGESTS).try_exists().unwrap());
+
+    let status = fixture.command().arg("--fix").status().unwrap();
+    assert!(status.success());
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&digests).unwrap(),
+        serde_json::from_str::<serde_json::Value>(
+            &read_to_string(fixture.path().join(DIGESTS)).unwrap()
+        )
+        .unwrap()
+    );
+}
+
+const INDEX: &str = r#"{
+  "package": {
+    "lib": {
+      "tests::a": ["src/lib.rs"],
+      "tests::b": ["src/lib.rs"]
+    }
+  }
+}"#;
+
+#[test]
+fn missing_lcov_is_reported() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n2\n")
+        .write_lcov("package", "lib", "tests::a", &[("src/lib.rs", &[1])])
+        .write_lcov("package", "lib", "tests::b", &[("src/lib.rs", &[1])])
+        .write_digests(&["src/lib.rs"])
+        .write_source("line-test.db/index.json", INDEX);
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--line", "src/lib.rs:1"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(!stderr.contains("missing or unreadable"), "{stderr}");
+
+    remove_file(
+        fixture
+            .path()
+            .join("line-test.db/packages/package/lib/tests::b.lcov"),
+    )
+    .unwrap();
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--line", "src/lib.rs:1"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(vec!["tests::a"], shown_tests(&output.stdout));
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(stderr.contains("missing or unreadable"), "{stderr}");
+    assert!(stderr.contains("test `tests::b`"), "{stderr}");
+    assert!(!stderr.contains("test `tests::a`"), "{stderr}");
+}
+
+const CARGO_LOCK_WARNING: &str = "Cargo.lock has changed since line-test.db was built";
+
+#[test]
+fn modified_cargo_lock_is_stale() {
+    let fixture = Fixture::built("my-package");
+
+    let output = fixture
+        .command()
+        .args(["--line", "src/lib.rs:2", "--no-run"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(!stderr.contains(CARGO_LOCK_WARNING), "{stderr}");
+
+    let cargo_lock = fixture.path().join("Cargo.lock");
+    let contents = read_to_string(&cargo_lock).unwrap();
+    write(&cargo_lock, format!("{contents}\n")).unwrap();
+
+    let output = fixture
+        .command()
+        .args(["--line", "src/lib.rs:2", "--no-run"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(stderr.contains(CARGO_LOCK_WARNING), "{stderr}");
+
+    // smoelius: No source file changed, but every test is refreshed.
+    let output = fixture
+        .command()
+        .args(["--refresh", "--no-run"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(vec!["tests::it_works"], shown_tests(&output.stdout));
+}
+
+const TEST_ARGS_WARNING: &str = "line-test.db was built with test binary arguments \
+                                 `--test-threads=1`, but tests are being run with none";
+
+#[test]
+fn changed_test_args_are_warned_about() {
+    let fixture = Fixture::copy("my-package");
+
+    let status = fixture
+        .command()
+        .args(["--build", "--", "--", "--test-threads=1"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let test_args = read_to_string(fixture.path().join("line-test.db/test-args.json")).unwrap();
+    assert_eq!(
+        vec!["--test-threads=1"],
+        serde_json::from_str::<Vec<String>>(&test_args).unwrap()
+    );
+
+    let output = fixture
+        .command()
+        .args(["--line", "src/lib.rs:2"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:#?}");
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(stderr.contains(TEST_ARGS_WARNING), "{stderr}");
+
+    let output = fixture
+        .command()
+        .args(["--line", "src/lib.rs:2", "--", "--", "--test-threads=1"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:#?}");
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(!stderr.contains("test binary arguments"), "{stderr}");
+}
+
+#[test]
+fn verify() {
+    let fixture = Fixture::built("my-package");
+
+    let output = fixture.command().arg("--verify").output().unwrap();
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(output.status.success(), "{stderr}");
+    assert!(stderr.contains("no problems found"), "{stderr}");
+
+    let lcov = fixture
+        .path()
+        .join("line-test.db/packages/my-package/lib/tests::it_works.lcov");
+    write(lcov, "DA:x,y\n").unwrap();
+
+    let output = fixture.command().arg("--verify").output().unwrap();
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(!output.status.success(), "{stderr}");
+    assert!(
+        stderr.contains("failed to read line-test.db/packages/my-package/lib/tests::it_works.lcov"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("found 1 problem(s)"), "{stderr}");
+}
+
+#[test]
+fn line_test_db_env() {
+    let fixture = Fixture::copy("my-package");
+    let db = fixture.path().join("cache/custom.db");
+
+    let status = fixture
+        .command()
+        .arg("--build")
+        .env("LINE_TEST_DB", &db)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(db.join("digests.json").try_exists().unwrap());
+    assert!(db
+        .join("packages/my-package/lib/tests::it_works.lcov")
+        .try_exists()
+        .unwrap());
+    assert!(!fixture.path().join("line-test.db").try_exists().unwrap());
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--line", "src/lib.rs:2"])
+        .env("LINE_TEST_DB", &db)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(vec!["tests::it_works"], shown_tests(&output.stdout));
+
+    // smoelius: Without `LINE_TEST_DB`, the default location is used, and it does not exist.
+    let output = fixture
+        .command()
+        .args(["--no-run", "--line", "src/lib.rs:2"])
+        .env_remove("LINE_TEST_DB")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
+
+#[test]
+fn db_path_precedence() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n")
+        .write_lcov_in(
+            "cli.db",
+            "package",
+            "lib",
+            "tests::cli",
+            &[("src/lib.rs", &[1])],
+        )
+        .write_digests_in("cli.db", &["src/lib.rs"])
+        .write_lcov_in(
+            "env.db",
+            "package",
+            "lib",
+            "tests::env",
+            &[("src/lib.rs", &[1])],
+        )
+        .write_digests_in("env.db", &["src/lib.rs"])
+        .write_lcov_in(
+            "config.db",
+            "package",
+            "lib",
+            "tests::config",
+            &[("src/lib.rs", &[1])],
+        )
+        .write_digests_in("config.db", &["src/lib.rs"]);
+    write(
+        fixture.path().join(".line-test.toml"),
+        "db_path = \"config.db\"\n",
+    )
+    .unwrap();
+
+    for (cli, env, expected) in [
+        (Some("cli.db"), Some("env.db"), "tests::cli"),
+        (None, Some("env.db"), "tests::env"),
+        (None, None, "tests::config"),
+    ] {
+        let mut command = fixture.command();
+        command.args(["--no-run", "--line", "src/lib.rs:1"]);
+        if let Some(cli) = cli {
+            command.args(["--db-path", cli]);
+        }
+        if let Some(env) = env {
+            command.env("LINE_TEST_DB", env);
+        } else {
+            command.env_remove("LINE_TEST_DB");
+        }
+        let output = command.output().unwrap();
+        assert!(output.status.success());
+        assert_eq!(vec![expected], shown_tests(&output.stdout));
+    }
+}
+
+#[test]
+fn dump_db_tree() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n2\n")
+        .write_lcov_contents(
+            "line-test.db",
+            "package",
+            "lib",
+            "tests::a",
+            "SF:$ROOT/src/lib.rs\nend_of_record\n",
+        )
+        .write_lcov("package", "lib", "tests::b", &[("src/lib.rs", &[1])])
+        .write_lcov("package", "it", "it_works", &[("src/lib.rs", &[2])])
+        .write_digests(&["src/lib.rs"])
+        .write_source("line-test.db/index.json", INDEX);
+
+    remove_file(
+        fixture
+            .path()
+            .join("line-test.db/packages/package/lib/tests::b.lcov"),
+    )
+    .unwrap();
+
+    let output = fixture.command().arg("--dump-db-tree").output().unwrap();
+    assert!(output.status.success());
+    let size = |test: &str| {
+        metadata(
+            fixture
+                .path()
+                .join(format!("line-test.db/packages/package/{test}.lcov")),
+        )
+        .unwrap()
+        .len()
+    };
+    assert_eq!(
+        format!(
+            "\
+package
+    it
+        it_works ({} bytes)
+    lib
+        tests::a ({} bytes)
+        tests::b (missing)
+",
+            size("it/it_works"),
+            size("lib/tests::a"),
+        ),
+        std::str::from_utf8(&output.stdout).unwrap()
+    );
+}
+
+#[test]
+fn list_covered_files() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/a.rs", "1\n2\n")
+        .write_source("src/b.rs", "1\n2\n")
+        .write_source("src/c.rs", "1\n2\n")
+        .write_lcov(
+            "package",
+            "lib",
+            "tests::x",
+            &[("src/a.rs", &[1]), ("src/b.rs", &[2])],
+        )
+        .write_lcov("package", "lib", "tests::y", &[("src/b.rs", &[1])])
+        .write_lcov_contents(
+            "line-test.db",
+            "package",
+            "lib",
+            "tests::z",
+            "SF:$ROOT/src/c.rs\nDA:1,0\nend_of_record\n",
+        )
+        .write_digests(&["src/a.rs", "src/b.rs", "src/c.rs"]);
+
+    let output = fixture
+        .command()
+        .arg("--list-covered-files")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        "src/a.rs: 1\nsrc/b.rs: 2\nsrc/c.rs: 0\n",
+        std::str::from_utf8(&output.stdout).unwrap()
+    );
+}
+
+#[test]
+fn tests_covering() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n2\n3\n")
+        .write_lcov("package", "lib", "tests::a", &[("src/lib.rs", &[1, 2])])
+        .write_lcov("package", "lib", "tests::b", &[("src/lib.rs", &[2, 3])])
+        .write_lcov("package", "lib", "tests::c", &[("src/lib.rs", &[3])])
+        .write_digests(&["src/lib.rs"]);
+
+    let output = fixture
+        .command()
+        .args(["--tests-covering", "src/lib.rs:2"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        "package lib tests::a\npackage lib tests::b\n",
+        std::str::from_utf8(&output.stdout).unwrap()
+    );
+}
+
+#[test]
+fn tests_covering_requires_line() {
+    let fixture = Fixture::new();
+
+    let output = fixture
+        .command()
+        .args(["--tests-covering", "src/lib.rs"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(std::str::from_utf8(&output.stderr)
+        .unwrap()
+        .contains("expected `PATH:LINE`"));
+}
+
+#[test]
+fn packages_below_threshold_are_listed() {
+    let fixture = Fixture::copy("workspace");
+    fixture
+        .write_lcov_contents(
+            "line-test.db",
+            "a",
+            "lib",
+            "tests::add_works",
+            "SF:a/src/lib.rs\nDA:1,1\nDA:2,1\nDA:3,0\nDA:10,1\nend_of_record\n",
+        )
+        .write_lcov_contents(
+            "line-test.db",
+            "b",
+            "lib",
+            "tests::add_works",
+            "SF:b/src/lib.rs\nDA:1,1\nDA:2,0\nDA:3,0\nDA:10,0\nend_of_record\n",
+        )
+        .write_digests(&["a/src/lib.rs", "b/src/lib.rs"]);
+
+    let output = fixture
+        .command()
+        .args(["--packages-with-coverage-below", "50"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:#?}");
+    assert_eq!(
+        "b: 25.00% (1/4)\n",
+        std::str::from_utf8(&output.stdout).unwrap()
+    );
+
+    let output = fixture
+        .command()
+        .args(["--packages-with-coverage-below", "80%"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:#?}");
+    assert_eq!(
+        "a: 75.00% (3/4)\nb: 25.00% (1/4)\n",
+        std::str::from_utf8(&output.stdout).unwrap()
+    );
+}
+
+#[test]
+fn invalid_threshold() {
+    let fixture = Fixture::copy("workspace");
+
+    let output = fixture
+        .command()
+        .args(["--packages-with-coverage-below", "101"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
@@ -0,0 +1,726 @@
+use std::{
+    fs::{rename, File},
+    os::unix::fs::symlink,
+    time::{Duration, SystemTime},
+};
+
+mod common;
+use common::{output_with_stdin, shown_tests, Fixture};
+
+#[test]
+fn column_specification_selects_by_line() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n2\n3\n")
+        .write_lcov("package", "lib", "tests::a", &[("src/lib.rs", &[1])])
+        .write_lcov("package", "lib", "tests::b", &[("src/lib.rs", &[2])])
+        .write_digests(&["src/lib.rs"]);
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--line", "src/lib.rs:2:1-3"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(vec!["tests::b"], shown_tests(&output.stdout));
+}
+
+#[test]
+fn file() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/x.rs", "1\n2\n3\n")
+        .write_source("src/y.rs", "1\n")
+        .write_lcov("package", "lib", "tests::first", &[("src/x.rs", &[1])])
+        .write_lcov("package", "lib", "tests::last", &[("src/x.rs", &[3])])
+        .write_lcov("package", "lib", "tests::other", &[("src/y.rs", &[1])])
+        .write_digests(&["src/x.rs", "src/y.rs"]);
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--file", "src/x.rs"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        vec!["tests::first", "tests::last"],
+        shown_tests(&output.stdout)
+    );
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--file", "src/x.rs", "--file", "src/y.rs"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        vec!["tests::first", "tests::last", "tests::other"],
+        shown_tests(&output.stdout)
+    );
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--file", "src/z.rs"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(
+        stderr.contains("the following paths do not exist") && stderr.contains("src/z.rs"),
+        "{stderr}"
+    );
+}
+
+const LCOV_EXECUTES_ONE: &str = "\
+SF:$ROOT/src/lib.rs
+FN:1,_RNvCs1Q2lLyK3udX_7fixture3one
+FN:5,_RNvCs1Q2lLyK3udX_7fixture3two
+FNDA:1,_RNvCs1Q2lLyK3udX_7fixture3one
+FNDA:0,_RNvCs1Q2lLyK3udX_7fixture3two
+DA:1,1
+DA:2,1
+DA:3,1
+end_of_record
+";
+
+const LCOV_EXECUTES_TWO: &str = "\
+SF:$ROOT/src/lib.rs
+FN:1,_RNvCs1Q2lLyK3udX_7fixture3one
+FN:5,_RNvCs1Q2lLyK3udX_7fixture3two
+FNDA:0,_RNvCs1Q2lLyK3udX_7fixture3one
+FNDA:1,_RNvCs1Q2lLyK3udX_7fixture3two
+DA:5,1
+DA:6,1
+DA:7,1
+end_of_record
+";
+
+#[test]
+fn select_by_function_name() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n2\n3\n4\n5\n6\n7\n")
+        .write_lcov_contents(
+            "line-test.db",
+            "package",
+            "lib",
+            "tests::a",
+            LCOV_EXECUTES_ONE,
+        )
+        .write_lcov_contents(
+            "line-test.db",
+            "package",
+            "lib",
+            "tests::b",
+            LCOV_EXECUTES_TWO,
+        )
+        .write_digests(&["src/lib.rs"]);
+
+    for (spec, expected) in [
+        ("src/lib.rs#one", "tests::a"),
+        ("src/lib.rs#two", "tests::b"),
+        ("src/lib.rs#fixture::two", "tests::b"),
+    ] {
+        let output = fixture
+            .command()
+            .args(["--no-run", "--line", spec])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(vec![expected], shown_tests(&output.stdout));
+    }
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--line", "src/lib.rs#wo"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(shown_tests(&output.stdout).is_empty());
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(
+        stderr.contains("the following functions are not covered by any test"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn function_specification_with_nonexistent_path() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n2\n3\n4\n5\n6\n7\n")
+        .write_lcov_contents(
+            "line-test.db",
+            "package",
+            "lib",
+            "tests::a",
+            LCOV_EXECUTES_ONE,
+        )
+        .write_digests(&["src/lib.rs"]);
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--line", "src/lbi.rs#one"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(
+        stderr.contains("the following paths do not exist: [\n    \"src/lbi.rs\",\n]"),
+        "{stderr}"
+    );
+}
+
+const SOURCE: &str = "\
+pub fn f(x: u32) -> u32 {
+    if x == 0 {
+        return 0;
+    }
+    x + 2
+}
+
+pub fn g() {}
+";
+
+const CHANGED_FUNCTION_DIFF: &str = "\
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -5,1 +5,1 @@
+-    x + 1
++    x + 2
+";
+
+// smoelius: tests::a returns early from `f`, so it does not cover the changed line, only `f`.
+const LCOV_A: &str = "\
+SF:$ROOT/src/lib.rs
+FN:1,_RNvCs1Q2lLyK3udX_7fixture1f
+FN:8,_RNvCs1Q2lLyK3udX_7fixture1g
+FNDA:1,_RNvCs1Q2lLyK3udX_7fixture1f
+FNDA:0,_RNvCs1Q2lLyK3udX_7fixture1g
+DA:1,1
+DA:2,1
+DA:3,1
+DA:5,0
+DA:8,0
+end_of_record
+";
+
+const LCOV_B: &str = "\
+SF:$ROOT/src/lib.rs
+FN:1,_RNvCs1Q2lLyK3udX_7fixture1f
+FN:8,_RNvCs1Q2lLyK3udX_7fixture1g
+FNDA:0,_RNvCs1Q2lLyK3udX_7fixture1f
+FNDA:1,_RNvCs1Q2lLyK3udX_7fixture1g
+DA:1,0
+DA:8,1
+end_of_record
+";
+
+fn changed_functions_fixture() -> Fixture {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", SOURCE)
+        .write_source("changes.diff", CHANGED_FUNCTION_DIFF)
+        .write_lcov_contents("line-test.db", "package", "lib", "tests::a", LCOV_A)
+        .write_lcov_contents("line-test.db", "package", "lib", "tests::b", LCOV_B)
+        .write_digests(&["src/lib.rs"]);
+    fixture
+}
+
+#[test]
+fn changed_line_is_not_covered() {
+    let fixture = changed_functions_fixture();
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--diff", "--diff-file", "changes.diff"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(shown_tests(&output.stdout).is_empty());
+}
+
+#[test]
+fn changed_function_is_covered() {
+    let fixture = changed_functions_fixture();
+
+    let output = fixture
+        .command()
+        .args([
+            "--no-run",
+            "--diff",
+            "--diff-file",
+            "changes.diff",
+            "--changed-functions",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(vec!["tests::a"], shown_tests(&output.stdout));
+}
+
+#[test]
+fn changed_functions_requires_diff() {
+    let fixture = changed_functions_fixture();
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--line", "src/lib.rs:5", "--changed-functions"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
+
+#[test]
+fn null_separated_specs() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/a b.rs", "1\n2\n")
+        .write_source("src/lib.rs", "1\n2\n3\n")
+        .write_lcov("package", "lib", "tests::a", &[("src/a b.rs", &[2])])
+        .write_lcov("package", "lib", "tests::b", &[("src/lib.rs", &[3])])
+        .write_lcov("package", "lib", "tests::c", &[("src/lib.rs", &[1])])
+        .write_digests(&["src/a b.rs", "src/lib.rs"]);
+
+    let mut command = fixture.command();
+    command.args(["--no-run", "--null", "--line", "-"]);
+    let output = output_with_stdin(command, "src/a b.rs\0src/lib.rs:3\0");
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(output.status.success(), "{stderr}");
+    assert_eq!(vec!["tests::a", "tests::b"], shown_tests(&output.stdout));
+}
+
+#[test]
+fn since() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/a.rs", "1\n2\n")
+        .write_source("src/b.rs", "1\n")
+        .write_lcov("package", "lib", "tests::a", &[("src/a.rs", &[2])])
+        .write_lcov("package", "lib", "tests::b", &[("src/b.rs", &[1])])
+        .write_digests(&["src/a.rs", "src/b.rs"]);
+
+    let two_hours_ago = SystemTime::now() - Duration::from_hours(2);
+    for path in ["src/a.rs", "src/b.rs"] {
+        File::options()
+            .write(true)
+            .open(fixture.path().join(path))
+            .unwrap()
+            .set_modified(two_hours_ago)
+            .unwrap();
+    }
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--since", "1h"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(shown_tests(&output.stdout).is_empty());
+
+    File::options()
+        .write(true)
+        .open(fixture.path().join("src/a.rs"))
+        .unwrap()
+        .set_modified(SystemTime::now())
+        .unwrap();
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--since", "1h"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(vec!["tests::a"], shown_tests(&output.stdout));
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--since", "3h"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(vec!["tests::a", "tests::b"], shown_tests(&output.stdout));
+}
+
+const A_RS_DIFF: &str = "\
+--- a/src/a.rs
++++ b/src/a.rs
+@@ -2,1 +2,1 @@
+-2
++two
+";
+
+#[test]
+fn diff_file_with_line_dash() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/a.rs", "1\n2\n3\n")
+        .write_source("src/b.rs", "1\n2\n3\n")
+        .write_source("changes.diff", A_RS_DIFF)
+        .write_lcov("package", "lib", "tests::a", &[("src/a.rs", &[2])])
+        .write_lcov("package", "lib", "tests::b", &[("src/b.rs", &[3])])
+        .write_lcov("package", "lib", "tests::c", &[("src/b.rs", &[1])])
+        .write_digests(&["src/a.rs", "src/b.rs"]);
+
+    let mut command = fixture.command();
+    command.args(["--no-run", "--diff", "--line", "-"]);
+    let output = output_with_stdin(command, "src/b.rs:3\n");
+    assert!(!output.status.success());
+
+    let mut command = fixture.command();
+    command.args([
+        "--no-run",
+        "--diff",
+        "--diff-file",
+        "changes.diff",
+        "--line",
+        "-",
+    ]);
+    let output = output_with_stdin(command, "src/b.rs:3\n");
+    assert!(output.status.success());
+    assert_eq!(vec!["tests::a", "tests::b"], shown_tests(&output.stdout));
+}
+
+#[test]
+fn overlapping_specs_from_arguments_and_stdin() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n2\n3\n4\n")
+        .write_lcov("package", "lib", "tests::a", &[("src/lib.rs", &[1])])
+        .write_lcov("package", "lib", "tests::b", &[("src/lib.rs", &[2])])
+        .write_lcov("package", "lib", "tests::c", &[("src/lib.rs", &[3])])
+        .write_lcov("package", "lib", "tests::d", &[("src/lib.rs", &[4])])
+        .write_digests(&["src/lib.rs"]);
+
+    let mut command = fixture.command();
+    command.args([
+        "--no-run",
+        "--line",
+        "src/lib.rs:1-2",
+        "--line",
+        "src/lib.rs:2",
+        "--line",
+        "-",
+    ]);
+    let output = output_with_stdin(command, "src/lib.rs:3\n");
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(output.status.success(), "{stderr}");
+    assert_eq!(
+        vec!["tests::a", "tests::b", "tests::c"],
+        shown_tests(&output.stdout)
+    );
+}
+
+const LIB_RS_DIFF: &str = "\
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -10,3 +10,3 @@
+-10
+-11
+-12
++ten
++eleven
++twelve
+";
+
+#[test]
+fn specs_from_arguments_and_diff() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n")
+        .write_lcov("package", "lib", "tests::a", &[("src/lib.rs", &[2])])
+        .write_lcov("package", "lib", "tests::b", &[("src/lib.rs", &[11])])
+        .write_lcov("package", "lib", "tests::c", &[("src/lib.rs", &[6])])
+        .write_digests(&["src/lib.rs"]);
+
+    let mut command = fixture.command();
+    command.args([
+        "--no-run",
+        "--line",
+        "src/lib.rs:1-3",
+        "--diff",
+        "--format",
+        "json",
+    ]);
+    let output = output_with_stdin(command, LIB_RS_DIFF);
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(output.status.success(), "{stderr}");
+    let value = serde_json::from_slice::<serde_json::Value>(&output.stdout).unwrap();
+    assert_eq!(
+        serde_json::json!({
+            "tests": [
+                { "package": "package", "crate": "lib", "test": "tests::a" },
+                { "package": "package", "crate": "lib", "test": "tests::b" },
+            ],
+            "uncovered": { "src/lib.rs": [[1, 2], [3, 4], [10, 11], [12, 13]] },
+        }),
+        value
+    );
+}
+
+const LCOV_TAKES_FIRST_BRANCH: &str = "\
+SF:$ROOT/src/lib.rs
+BRDA:2,0,0,1
+BRDA:2,0,1,-
+DA:1,1
+DA:2,1
+DA:3,1
+end_of_record
+";
+
+const LCOV_TAKES_SECOND_BRANCH: &str = "\
+SF:$ROOT/src/lib.rs
+BRDA:2,0,0,-
+BRDA:2,0,1,1
+DA:1,1
+DA:2,1
+DA:4,1
+end_of_record
+";
+
+fn branches_fixture() -> Fixture {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n2\n3\n4\n")
+        .write_lcov_contents(
+            "line-test.db",
+            "package",
+            "lib",
+            "tests::a",
+            LCOV_TAKES_FIRST_BRANCH,
+        )
+        .write_lcov_contents(
+            "line-test.db",
+            "package",
+            "lib",
+            "tests::b",
+            LCOV_TAKES_SECOND_BRANCH,
+        )
+        .write_digests(&["src/lib.rs"]);
+    fixture
+}
+
+#[test]
+fn branch_selection_differs_from_line_selection() {
+    let fixture = branches_fixture();
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--line", "src/lib.rs:2"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(vec!["tests::a", "tests::b"], shown_tests(&output.stdout));
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--branches", "src/lib.rs:2:1"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(vec!["tests::b"], shown_tests(&output.stdout));
+}
+
+#[test]
+fn untaken_branch_warning() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n2\n3\n4\n")
+        .write_lcov_contents(
+            "line-test.db",
+            "package",
+            "lib",
+            "tests::a",
+            LCOV_TAKES_FIRST_BRANCH,
+        )
+        .write_digests(&["src/lib.rs"]);
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--branches", "src/lib.rs:2:0-1"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(vec!["tests::a"], shown_tests(&output.stdout));
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(
+        stderr.contains("the following branches are not taken by any test:\n    src/lib.rs:2:1\n"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn ignored_lines_select_no_tests() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source(".line-test-ignore", "src/generated/**\n")
+        .write_source("src/lib.rs", "1\n2\n")
+        .write_source("src/generated/a.rs", "1\n2\n")
+        .write_lcov(
+            "package",
+            "lib",
+            "tests::a",
+            &[("src/lib.rs", &[1]), ("src/generated/a.rs", &[1])],
+        )
+        .write_lcov(
+            "package",
+            "lib",
+            "tests::b",
+            &[("src/generated/a.rs", &[2])],
+        )
+        .write_digests(&["src/lib.rs", "src/generated/a.rs"]);
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--line", "src/generated/a.rs:1-2"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(shown_tests(&output.stdout).is_empty());
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(
+        stderr.contains("the following paths match a pattern in .line-test-ignore"),
+        "{stderr}"
+    );
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--line", "src/lib.rs:1"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(vec!["tests::a"], shown_tests(&output.stdout));
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--zero-coverage"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(vec!["tests::b"], shown_tests(&output.stdout));
+}
+
+const MANIFEST: &str = r#"
+[package]
+name = "package"
+version = "0.1.0"
+edition = "2021"
+publish = false
+"#;
+
+#[test]
+fn target_path_warning() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("Cargo.toml", MANIFEST)
+        .write_source("src/lib.rs", "1\n2\n3\n")
+        .write_source("target/debug/build/package/out/generated.rs", "1\n2\n3\n")
+        .write_lcov("package", "lib", "tests::a", &[("src/lib.rs", &[1, 2])])
+        .write_digests(&["src/lib.rs"]);
+
+    let output = fixture
+        .command()
+        .args([
+            "--no-run",
+            "--line",
+            "target/debug/build/package/out/generated.rs:2",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(
+        stderr.contains("are in the target directory or outside the workspace"),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("are not covered by any test"), "{stderr}");
+}
+
+// smoelius: The fixture's src directory is moved to real-src and replaced with a symlink to it.
+// Coverage files record paths with symlinks resolved, and specified paths are resolved the same
+// way, so either spelling selects the test.
+#[test]
+fn symlinked_source_directory() {
+    let fixture = Fixture::copy("my-package");
+    rename(fixture.path().join("src"), fixture.path().join("real-src")).unwrap();
+    symlink("real-src", fixture.path().join("src")).unwrap();
+
+    let status = fixture.command().arg("--build").status().unwrap();
+    assert!(status.success());
+
+    for spec in ["src/lib.rs:2", "real-src/lib.rs:2", "./src/lib.rs:2"] {
+        let output = fixture
+            .command()
+            .args(["--no-run", "--line", spec])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{output:#?}");
+        assert_eq!(
+            vec!["tests::it_works"],
+            shown_tests(&output.stdout),
+            "{spec}"
+        );
+    }
+}
+
+fn nothing_to_do_fixture() -> Fixture {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n2\n")
+        .write_source("src/main.rs", "1\n2\n")
+        .write_lcov("package", "lib", "tests::a", &[("src/lib.rs", &[1])])
+        .write_digests(&["src/lib.rs"]);
+    fixture
+}
+
+#[test]
+fn specified_lines_are_not_covered() {
+    let fixture = nothing_to_do_fixture();
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--line", "src/lib.rs:2"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(shown_tests(&output.stdout).is_empty());
+    assert!(std::str::from_utf8(&output.stderr)
+        .unwrap()
+        .contains("Nothing to do: the specified lines are not covered by any test\n"));
+}
+
+#[test]
+fn covering_tests_are_excluded() {
+    let fixture = nothing_to_do_fixture();
+
+    let output = fixture
+        .command()
+        .args([
+            "--no-run",
+            "--line",
+            "src/lib.rs:1",
+            "--exclude-test",
+            "tests::*",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(std::str::from_utf8(&output.stderr)
+        .unwrap()
+        .contains("Nothing to do: the tests covering the specified lines were all excluded"));
+}
+
+#[test]
+fn path_not_in_db() {
+    let fixture = nothing_to_do_fixture();
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--line", "src/main.rs:1"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(stderr.contains("the following paths are not covered by any test"));
+    assert!(stderr.ends_with("Nothing to do\n"), "{stderr}");
+}
@@ -0,0 +1,611 @@
+use serde_json::{json, Value};
+use std::{
+    env::temp_dir,
+    fs::{read_dir, read_to_string, remove_dir_all},
+};
+
+mod common;
+use common::{git, output_with_stdin, Fixture};
+
+// smoelius: The report writers are unit tested in their modules. The tests below check that each
+// report is written from a real database. In the my-package fixture, `tests::it_works` covers lines
+// 1-3 and 10-13 of src/lib.rs, and no test covers lines 4-9 and 14.
+
+#[test]
+fn cobertura() {
+    let fixture = Fixture::built("my-package");
+
+    let status = fixture
+        .command()
+        .args(["--cobertura", "entire.xml"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    // smoelius: Each instrumented line is executed by some test.
+    let xml = read_to_string(fixture.path().join("entire.xml")).unwrap();
+    assert!(xml.contains(r#"<coverage line-rate="1""#), "{xml}");
+    assert!(
+        xml.contains(r#"lines-covered="7" lines-valid="7""#),
+        "{xml}"
+    );
+
+    let status = fixture
+        .command()
+        .args([
+            "--cobertura",
+            "lines.xml",
+            "--no-run",
+            "--line",
+            "src/lib.rs:1-4",
+        ])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let xml = read_to_string(fixture.path().join("lines.xml")).unwrap();
+    assert!(
+        xml.contains(r#"<class name="lib.rs" filename="src/lib.rs" line-rate="0.75""#),
+        "{xml}"
+    );
+    assert!(xml.contains(r#"<line number="3" hits="1"/>"#), "{xml}");
+    assert!(xml.contains(r#"<line number="4" hits="0"/>"#), "{xml}");
+}
+
+#[test]
+fn sarif() {
+    let fixture = Fixture::built("my-package");
+
+    let status = fixture
+        .command()
+        .args(["--sarif", "out.sarif", "--no-run", "--line", "src/lib.rs"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let contents = read_to_string(fixture.path().join("out.sarif")).unwrap();
+    let value = serde_json::from_str::<Value>(&contents).unwrap();
+    let regions = value["runs"][0]["results"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|result| {
+            let physical_location = &result["locations"][0]["physicalLocation"];
+            assert_eq!("src/lib.rs", physical_location["artifactLocation"]["uri"]);
+            let region = &physical_location["region"];
+            (
+                region["startLine"].as_u64().unwrap(),
+                region["endLine"].as_u64().unwrap(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(vec![(4, 9), (14, 14)], regions);
+}
+
+#[test]
+fn html() {
+    let fixture = Fixture::built("my-package");
+
+    let status = fixture
+        .command()
+        .args([
+            "--html",
+            "report.html",
+            "--no-run",
+            "--line",
+            "src/lib.rs:1-4",
+        ])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let html = read_to_string(fixture.path().join("report.html")).unwrap();
+    for expected in [
+        "<h2><code>src/lib.rs</code></h2>",
+        r#"<tr class="covered"><td>1-3</td><td><code>my-package lib tests::it_works</code></td></tr>"#,
+        r#"<tr class="uncovered"><td>4</td><td>none</td></tr>"#,
+    ] {
+        assert!(html.contains(expected), "{expected}\n{html}");
+    }
+}
+
+#[test]
+fn uncovered_json() {
+    let fixture = Fixture::built("my-package");
+
+    let output = fixture
+        .command()
+        .args([
+            "--uncovered-json",
+            "uncovered.json",
+            "--no-run",
+            "--line",
+            "src/lib.rs",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let contents = read_to_string(fixture.path().join("uncovered.json")).unwrap();
+    let value = serde_json::from_str::<Value>(&contents).unwrap();
+    assert_eq!(json!({ "src/lib.rs": [[4, 10], [14, 15]] }), value);
+
+    // smoelius: The JSON is written even if the uncovered lines cause an error.
+    let output = fixture
+        .command()
+        .args([
+            "--uncovered-json",
+            "strict.json",
+            "--deny-warnings",
+            "--no-run",
+            "--line",
+            "src/lib.rs:14",
+        ])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let contents = read_to_string(fixture.path().join("strict.json")).unwrap();
+    let value = serde_json::from_str::<Value>(&contents).unwrap();
+    assert_eq!(json!({ "src/lib.rs": [[14, 15]] }), value);
+}
+
+#[test]
+fn coverage_report() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n2\n3\n4\n5\n6\n")
+        .write_source("src/main.rs", "1\n2\n")
+        .write_lcov(
+            "package",
+            "lib",
+            "tests::a",
+            &[("src/lib.rs", &[1, 2]), ("src/main.rs", &[1, 2])],
+        )
+        .write_lcov("package", "lib", "tests::b", &[("src/lib.rs", &[2])])
+        .write_digests(&["src/lib.rs", "src/main.rs"]);
+
+    let output = fixture
+        .command()
+        .args([
+            "--no-run",
+            "--coverage-report",
+            "--line",
+            "src/lib.rs:1-4",
+            "--line",
+            "src/main.rs:1-2",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = std::str::from_utf8(&output.stdout).unwrap();
+    assert!(
+        stdout.contains(
+            "\
+src/lib.rs: 50.00% (2/4)
+src/main.rs: 100.00% (2/2)
+total: 66.67% (4/6)
+"
+        ),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn instrumented_lines_only() {
+    let fixture = Fixture::built("my-package");
+
+    // smoelius: Of src/lib.rs's 14 lines, only the 7 that `tests::it_works` covers are
+    // instrumented.
+    for (args, expected) in [
+        (&[][..], "src/lib.rs: 50.00% (7/14)\n"),
+        (
+            &["--instrumented-lines-only"],
+            "src/lib.rs: 100.00% (7/7)\n",
+        ),
+    ] {
+        let output = fixture
+            .command()
+            .args(["--no-run", "--coverage-report", "--line", "src/lib.rs"])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{output:#?}");
+        let stdout = std::str::from_utf8(&output.stdout).unwrap();
+        assert!(stdout.starts_with(expected), "{stdout}");
+    }
+}
+
+#[test]
+fn json_output_file() {
+    let fixture = Fixture::built("my-package");
+
+    let output = fixture
+        .command()
+        .args([
+            "--format",
+            "json",
+            "--output",
+            "out.json",
+            "--no-run",
+            "--line",
+            "src/lib.rs:2,14",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+
+    let contents = read_to_string(fixture.path().join("out.json")).unwrap();
+    let value = serde_json::from_str::<Value>(&contents).unwrap();
+    assert_eq!(
+        json!({
+            "tests": [{ "package": "my-package", "crate": "lib", "test": "tests::it_works" }],
+            "uncovered": { "src/lib.rs": [[14, 15]] },
+        }),
+        value
+    );
+}
+
+#[test]
+fn human_output_file_lists_uncovered_lines() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n2\n3\n")
+        .write_lcov("package", "lib", "tests::a", &[("src/lib.rs", &[1])])
+        .write_digests(&["src/lib.rs"]);
+
+    let output = fixture
+        .command()
+        .args([
+            "--output",
+            "out.txt",
+            "--no-run",
+            "--line",
+            "src/lib.rs:1,3",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(!stderr.contains("not covered"), "{stderr}");
+
+    let contents = read_to_string(fixture.path().join("out.txt")).unwrap();
+    assert!(
+        contents.contains(
+            "Warning: the following lines are not covered by any test:\n    src/lib.rs:3\n"
+        ),
+        "{contents}"
+    );
+    assert!(contents.contains("--exact 'tests::a'"), "{contents}");
+}
+
+#[test]
+fn dump_coverage() {
+    let fixture = Fixture::built("my-package");
+
+    let output = fixture
+        .command()
+        .args(["--dump-coverage", "coverage.json"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+
+    let contents = read_to_string(fixture.path().join("coverage.json")).unwrap();
+    let value = serde_json::from_str::<Value>(&contents).unwrap();
+    assert_eq!(
+        json!({
+            "my-package": {
+                "lib": {
+                    "tests::it_works": { "src/lib.rs": [1, 2, 3, 10, 11, 12, 13] },
+                },
+            },
+        }),
+        value
+    );
+}
+
+const DIFF: &str = "\
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -2,2 +2,2 @@
+-2
+-3
++two
++three
+@@ -5,1 +5,1 @@
+-5
++five
+";
+
+#[test]
+fn summarize_diff() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n2\n3\n4\n5\n6\n")
+        .write_lcov("package", "lib", "tests::a", &[("src/lib.rs", &[1, 2, 3])])
+        .write_digests(&["src/lib.rs"]);
+
+    // smoelius: The diff's paths are resolved before being compared with the uncovered lines'
+    // paths.
+    let dotted_diff = DIFF
+        .replace("a/src/", "a/./src/")
+        .replace("b/src/", "b/./src/");
+
+    for diff in [DIFF, &dotted_diff] {
+        let mut command = fixture.command();
+        command.args(["--no-run", "--diff", "--summarize-diff"]);
+        let output = output_with_stdin(command, diff);
+        assert!(output.status.success());
+
+        let stderr = std::str::from_utf8(&output.stderr).unwrap();
+        assert!(
+            stderr.contains(
+                "\
+src/lib.rs
+    covered:     2-3
+    not covered: 5
+"
+            ),
+            "{stderr}"
+        );
+    }
+}
+
+const CARGO_TOML: &str = r#"
+[package]
+name = "delta"
+version = "0.1.0"
+edition = "2021"
+publish = false
+"#;
+
+const LIB_RS: &str = "\
+pub fn one() -> u32 {
+    1
+}
+
+pub fn two() -> u32 {
+    2
+}
+
+#[test]
+fn test_one() {
+    assert_eq!(one(), 1);
+}
+
+#[test]
+fn test_two() {
+    assert_eq!(two(), 2);
+}
+";
+
+#[test]
+fn lost_coverage() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("Cargo.toml", CARGO_TOML)
+        .write_source(".gitignore", "/line-test.db\n/target\n")
+        .write_source("src/lib.rs", LIB_RS);
+
+    git(fixture.path(), &["init", "--quiet"]);
+    git(fixture.path(), &["add", "."]);
+    git(
+        fixture.path(),
+        &["commit", "--quiet", "--message", "Initial commit"],
+    );
+    let sha = git(fixture.path(), &["rev-parse", "HEAD"]);
+
+    // smoelius: Change line 6, in `two`, and remove `test_two`, the only test that covers `two`.
+    let (lib_rs, _) = LIB_RS.split_at(LIB_RS.rfind("\n#[test]").unwrap() + 1);
+    fixture.write_source("src/lib.rs", &lib_rs.replace("    2\n", "    1 + 1\n"));
+
+    let status = fixture.command().arg("--build").status().unwrap();
+    assert!(status.success());
+
+    let output = fixture
+        .command()
+        .args(["--coverage-delta", &sha])
+        .output()
+        .unwrap();
+
+    for entry in read_dir(temp_dir()).unwrap() {
+        let entry = entry.unwrap();
+        if entry
+            .file_name()
+            .to_string_lossy()
+            .starts_with(&format!("cargo-line-test-{sha}-"))
+        {
+            remove_dir_all(entry.path()).unwrap();
+        }
+    }
+
+    assert!(output.status.success());
+    let stdout = std::str::from_utf8(&output.stdout).unwrap();
+    assert_eq!("lost: src/lib.rs:6\n", stdout);
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(stderr.contains("lost coverage since"), "{stderr}");
+
+    // smoelius: The base's worktree was removed.
+    assert_eq!(
+        1,
+        git(fixture.path(), &["worktree", "list", "--porcelain"])
+            .lines()
+            .filter(|line| line.starts_with("worktree "))
+            .count()
+    );
+}
+
+#[test]
+fn coverage_delta_without_db() {
+    let fixture = Fixture::new();
+
+    let output = fixture
+        .command()
+        .args(["--coverage-delta", "HEAD"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(
+        stderr.contains("does not exist; run with `--build` to build it"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn baseline_differences() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n2\n3\n4\n5\n6\n")
+        .write_lcov_in(
+            "baseline.db",
+            "package",
+            "lib",
+            "tests::a",
+            &[("src/lib.rs", &[1, 2])],
+        )
+        .write_lcov_in(
+            "baseline.db",
+            "package",
+            "lib",
+            "tests::b",
+            &[("src/lib.rs", &[3])],
+        )
+        .write_lcov_in(
+            "baseline.db",
+            "package",
+            "lib",
+            "tests::c",
+            &[("src/lib.rs", &[4])],
+        )
+        .write_digests_in("baseline.db", &["src/lib.rs"])
+        .write_lcov("package", "lib", "tests::a", &[("src/lib.rs", &[2, 4, 5])])
+        .write_lcov("package", "lib", "tests::b", &[("src/lib.rs", &[3])])
+        .write_lcov("package", "lib", "tests::d", &[("src/lib.rs", &[6])])
+        .write_digests(&["src/lib.rs"]);
+
+    let output = fixture
+        .command()
+        .args(["--baseline", "baseline.db"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        "\
+changed: package lib tests::a
+    src/lib.rs: +4-5 -1
+removed: package lib tests::c
+added: package lib tests::d
+",
+        std::str::from_utf8(&output.stdout).unwrap()
+    );
+}
+
+#[test]
+fn covered_changes_pass() {
+    let fixture = built_fixture();
+
+    // smoelius: Line 2 is in `add`, which `tests::it_works` covers.
+    let lib_rs = read_to_string(fixture.path().join("src/lib.rs")).unwrap();
+    fixture.write_source(
+        "src/lib.rs",
+        &lib_rs.replace("    left + right", "    right + left"),
+    );
+
+    let output = fixture
+        .command()
+        .args([
+            "--diff",
+            "--diff-base",
+            "HEAD",
+            "--only-changed-lines-covered",
+        ])
+        .output()
+        .unwrap();
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(output.status.success(), "{stderr}");
+    assert!(!stderr.contains("not covered by any test"), "{stderr}");
+}
+
+#[test]
+fn uncovered_changes_fail() {
+    let fixture = built_fixture();
+
+    // smoelius: Line 5 is `#[cfg(test)]`, which no test covers.
+    let lib_rs = read_to_string(fixture.path().join("src/lib.rs")).unwrap();
+    fixture.write_source(
+        "src/lib.rs",
+        &lib_rs.replace("#[cfg(test)]", "#[cfg(all(test))]"),
+    );
+
+    let output = fixture
+        .command()
+        .args([
+            "--diff",
+            "--diff-base",
+            "HEAD",
+            "--only-changed-lines-covered",
+        ])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(
+        stderr.contains(
+            "Error: the following changed lines are not covered by any test:\n    src/lib.rs:5"
+        ),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn uncovered_insertions_fail() {
+    let fixture = built_fixture();
+
+    // smoelius: The diff only inserts lines, so it changes no lines of the original file. The
+    // inserted lines, 15 through 18, are not covered by any test.
+    let lib_rs = read_to_string(fixture.path().join("src/lib.rs")).unwrap();
+    fixture.write_source(
+        "src/lib.rs",
+        &(lib_rs + "\npub fn sub(left: u64, right: u64) -> u64 {\n    left - right\n}\n"),
+    );
+
+    let output = fixture
+        .command()
+        .args([
+            "--diff",
+            "--diff-base",
+            "HEAD",
+            "--only-changed-lines-covered",
+        ])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(
+        stderr.contains(
+            "Error: the following changed lines are not covered by any test:\n    src/lib.rs:15-18"
+        ),
+        "{stderr}"
+    );
+}
+
+/// Returns a copy of the my-package fixture, committed to a new git repository, with a built
+/// line-test.db.
+fn built_fixture() -> Fixture {
+    let fixture = Fixture::copy("my-package");
+    fixture.write_source(".gitignore", "/line-test.db\n/target\n");
+
+    git(fixture.path(), &["init", "--quiet"]);
+    git(fixture.path(), &["add", "."]);
+    git(
+        fixture.path(),
+        &["commit", "--quiet", "--message", "Initial commit"],
+    );
+
+    let status = fixture.command().arg("--build").status().unwrap();
+    assert!(status.success());
+
+    fixture
+}
@@ -0,0 +1,402 @@
+use std::{
+    io::{BufRead, BufReader},
+    process::{Child, Command, Stdio},
+    sync::mpsc::{channel, Receiver, RecvTimeoutError},
+    thread::spawn,
+    time::Duration,
+};
+
+mod common;
+use common::{git, shown_tests, Fixture};
+
+#[test]
+fn cargo_and_test_binary_args_are_separated() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n")
+        .write_lcov("package", "lib", "tests::it_works", &[("src/lib.rs", &[1])])
+        .write_digests(&["src/lib.rs"]);
+
+    for (args, expected) in [
+        (
+            &["--features", "extra", "--", "--test-threads", "1"][..],
+            " --features extra -- --test-threads 1 --exact 'tests::it_works'",
+        ),
+        (
+            &["--features", "extra"],
+            " --features extra -- --exact 'tests::it_works'",
+        ),
+        (
+            &["--", "--nocapture"],
+            " -- --nocapture --exact 'tests::it_works'",
+        ),
+    ] {
+        let output = fixture
+            .command()
+            .args(["--no-run", "--line", "src/lib.rs:1", "--"])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = std::str::from_utf8(&output.stdout).unwrap();
+        assert!(stdout.trim_end().ends_with(expected), "{stdout}");
+        assert_eq!(1, stdout.matches(" -- ").count(), "{stdout}");
+    }
+}
+
+#[test]
+fn shown_command_is_shell_parseable() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n")
+        .write_lcov("package", "lib", "tests::it's", &[("src/lib.rs", &[1])])
+        .write_digests(&["src/lib.rs"]);
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--line", "src/lib.rs:1"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = std::str::from_utf8(&output.stdout).unwrap();
+    let line = stdout.trim_end();
+
+    let (cd, command) = line.split_once(" && ").unwrap();
+    assert_eq!(format!("cd {}", fixture.path().display()), cd);
+
+    // smoelius: Have the shell parse the command's words and print them one per line.
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(format!("for word in {command}; do echo \"$word\"; done"))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let words = std::str::from_utf8(&output.stdout).unwrap();
+    assert!(words.ends_with("\n--exact\ntests::it's\n"), "{words}");
+}
+
+#[test]
+fn no_run_output_is_sorted() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n2\n")
+        .write_lcov("package", "lib", "tests::a", &[("src/lib.rs", &[])])
+        .write_lcov("package", "lib", "tests::b", &[("src/lib.rs", &[1])])
+        .write_lcov("package", "lib", "tests::c", &[("src/lib.rs", &[2])])
+        .write_lcov("other", "lib", "tests::d", &[("src/lib.rs", &[1])])
+        .write_digests(&["src/lib.rs"]);
+
+    let mut stdouts = Vec::new();
+    for _ in 0..2 {
+        // smoelius: --zero-coverage's tests are merged after those covering the specified lines.
+        let output = fixture
+            .command()
+            .args(["--no-run", "--line", "src/lib.rs:1", "--zero-coverage"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(
+            stdout.lines().all(|line| line.starts_with("cd ")),
+            "{stdout}"
+        );
+        stdouts.push(stdout);
+    }
+
+    assert_eq!(stdouts[0], stdouts[1]);
+    assert_eq!(
+        vec!["tests::d", "tests::a", "tests::b"],
+        shown_tests(stdouts[0].as_bytes())
+    );
+}
+
+#[test]
+fn list_groups_tests_by_package_and_crate() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n")
+        .write_lcov("alpha", "lib", "tests::b", &[("src/lib.rs", &[1])])
+        .write_lcov("alpha", "lib", "tests::a", &[("src/lib.rs", &[1])])
+        .write_lcov("alpha", "integration", "it", &[("src/lib.rs", &[1])])
+        .write_lcov("be", "lib", "tests::c", &[("src/lib.rs", &[1])])
+        .write_digests(&["src/lib.rs"]);
+
+    let output = fixture
+        .command()
+        .args(["--list", "--line", "src/lib.rs:1"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:#?}");
+    assert_eq!(
+        "\
+alpha  3 tests
+  integration  1 test
+    it
+  lib          2 tests
+    tests::a
+    tests::b
+be     1 test
+  lib          1 test
+    tests::c
+",
+        std::str::from_utf8(&output.stdout).unwrap()
+    );
+}
+
+#[test]
+fn list_built_db() {
+    let fixture = Fixture::built("my-package");
+
+    let output = fixture
+        .command()
+        .args(["--list", "--line", "src/lib.rs:2"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:#?}");
+    assert_eq!(
+        "\
+my-package  1 test
+  lib  1 test
+    tests::it_works
+",
+        std::str::from_utf8(&output.stdout).unwrap()
+    );
+}
+
+#[test]
+fn verbose_explains_selection() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n2\n3\n4\n5\n6\n")
+        .write_lcov("package", "lib", "tests::a", &[("src/lib.rs", &[1, 2, 3])])
+        .write_lcov("package", "lib", "tests::b", &[("src/lib.rs", &[3, 4])])
+        .write_lcov("package", "lib", "tests::c", &[("src/lib.rs", &[6])])
+        .write_digests(&["src/lib.rs"]);
+
+    let output = fixture
+        .command()
+        .args([
+            "--no-run",
+            "--verbose",
+            "--line",
+            "src/lib.rs:2-3",
+            "--min-lines",
+            "2",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(vec!["tests::a"], shown_tests(&output.stdout));
+
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(
+        stderr.contains(
+            "package lib tests::a
+    src/lib.rs: covered 2-3; not covered none
+    selected: covers 2 of the specified lines
+"
+        ),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains(
+            "package lib tests::b
+    src/lib.rs: covered 3; not covered 2
+    not selected: covers 1 of the specified lines, fewer than --min-lines
+"
+        ),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains(
+            "package lib tests::c
+    src/lib.rs: covered none; not covered 2-3
+    not selected: covers no specified lines
+"
+        ),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn selection_is_not_explained_without_verbose() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n2\n3\n")
+        .write_lcov("package", "lib", "tests::a", &[("src/lib.rs", &[2])])
+        .write_digests(&["src/lib.rs"]);
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--line", "src/lib.rs:2"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(!std::str::from_utf8(&output.stderr)
+        .unwrap()
+        .contains("selected"));
+}
+
+const CARGO_TOML: &str = r#"
+[package]
+name = "tap"
+version = "0.1.0"
+edition = "2021"
+publish = false
+
+[workspace]
+"#;
+
+const LIB_RS: &str = "\
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn pass() {}
+
+    #[test]
+    fn fail() {
+        panic!();
+    }
+}
+";
+
+#[test]
+fn tap() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("Cargo.toml", CARGO_TOML)
+        .write_source("src/lib.rs", LIB_RS)
+        .write_lcov("tap", "lib", "tests::fail", &[("src/lib.rs", &[7, 8])])
+        .write_lcov("tap", "lib", "tests::pass", &[("src/lib.rs", &[4])])
+        .write_digests(&["src/lib.rs"]);
+
+    let output = fixture
+        .command()
+        .args(["--format", "tap", "--line", "src/lib.rs:4-8"])
+        .output()
+        .unwrap();
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(output.status.success(), "{stderr}");
+
+    let stdout = std::str::from_utf8(&output.stdout).unwrap();
+    let lines = stdout.lines().collect::<Vec<_>>();
+    assert_eq!(
+        vec![
+            "1..2",
+            "not ok 1 - tap lib tests::fail",
+            "ok 2 - tap lib tests::pass"
+        ],
+        lines
+    );
+}
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[test]
+fn watch() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n2\n3\n")
+        .write_lcov("package", "lib", "tests::a", &[("src/lib.rs", &[1, 2])])
+        .write_digests(&["src/lib.rs"]);
+
+    let (mut child, rx) = spawn_watch(&fixture, &["--line", "src/lib.rs:2"]);
+
+    let line = rx.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(vec!["tests::a"], shown_tests(line.as_bytes()));
+
+    fixture.write_source("src/lib.rs", "1\n2\n3\n4\n");
+
+    let line = rx.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(vec!["tests::a"], shown_tests(line.as_bytes()));
+
+    assert_eq!(
+        Err(RecvTimeoutError::Timeout),
+        rx.recv_timeout(Duration::from_secs(2))
+    );
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+}
+
+#[test]
+fn watch_diff_base() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n2\n3\n4\n")
+        .write_lcov("package", "lib", "tests::a", &[("src/lib.rs", &[2])])
+        .write_lcov("package", "lib", "tests::b", &[("src/lib.rs", &[3])])
+        .write_digests(&["src/lib.rs"]);
+    git(fixture.path(), &["init", "--quiet"]);
+    git(fixture.path(), &["add", "src/lib.rs"]);
+    git(
+        fixture.path(),
+        &["commit", "--quiet", "-m", "Initial commit"],
+    );
+
+    fixture.write_source("src/lib.rs", "1\ntwo\n3\n4\n");
+
+    let (mut child, rx) = spawn_watch(&fixture, &["--diff", "--diff-base", "HEAD"]);
+
+    let line = rx.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(vec!["tests::a"], shown_tests(line.as_bytes()));
+
+    // smoelius: The diff is re-read, so the newly changed line 3 selects `tests::b` too.
+    fixture.write_source("src/lib.rs", "1\ntwo\nthree\n4\n");
+
+    let line = rx.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(vec!["tests::a"], shown_tests(line.as_bytes()));
+    let line = rx.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(vec!["tests::b"], shown_tests(line.as_bytes()));
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+}
+
+#[test]
+fn watch_diff_from_stdin() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n2\n3\n")
+        .write_lcov("package", "lib", "tests::a", &[("src/lib.rs", &[1, 2])])
+        .write_digests(&["src/lib.rs"]);
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--diff", "--watch"])
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(std::str::from_utf8(&output.stderr)
+        .unwrap()
+        .contains("--watch cannot be used with a diff read from standard input"));
+}
+
+/// Spawns `cargo line-test --no-run --watch` with `args`, and returns the child along with a
+/// receiver of the lines it writes to standard output.
+fn spawn_watch(fixture: &Fixture, args: &[&str]) -> (Child, Receiver<String>) {
+    let mut child = fixture
+        .command()
+        .args(["--no-run", "--watch"])
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let (tx, rx) = channel();
+    let stdout = child.stdout.take().unwrap();
+    spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    (child, rx)
+}
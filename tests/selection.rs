@@ -0,0 +1,645 @@
+mod common;
+use common::{output_with_stdin, shown_tests, Fixture};
+
+// smoelius: In the my-package fixture, `tests::it_works` covers line 2 of src/lib.rs, which is in
+// `add`, but not line 5, which is `#[cfg(test)]`.
+#[test]
+fn select_from_built_db() {
+    let fixture = Fixture::built("my-package");
+
+    for (spec, expected) in [
+        ("src/lib.rs:2", &["tests::it_works"][..]),
+        ("src/lib.rs:5", &[]),
+    ] {
+        let output = fixture
+            .command()
+            .args(["--no-run", "--line", spec])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{output:#?}");
+        assert_eq!(expected, shown_tests(&output.stdout));
+    }
+}
+
+#[test]
+fn exact_lines() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n2\n3\n4\n")
+        .write_source("src/main.rs", "1\n")
+        .write_lcov(
+            "package",
+            "lib",
+            "tests::exact",
+            &[("src/lib.rs", &[2, 3]), ("src/main.rs", &[1])],
+        )
+        .write_lcov("package", "lib", "tests::subset", &[("src/lib.rs", &[2])])
+        .write_lcov(
+            "package",
+            "lib",
+            "tests::superset",
+            &[("src/lib.rs", &[1, 2, 3])],
+        )
+        .write_digests(&["src/lib.rs", "src/main.rs"]);
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--line", "src/lib.rs:2-3"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        vec!["tests::exact", "tests::subset", "tests::superset"],
+        shown_tests(&output.stdout)
+    );
+
+    // smoelius: `tests::exact`'s coverage of files other than the specified one is irrelevant.
+    let output = fixture
+        .command()
+        .args(["--no-run", "--line", "src/lib.rs:2-3", "--exact-lines"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(vec!["tests::exact"], shown_tests(&output.stdout));
+}
+
+#[test]
+fn min_lines() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n2\n3\n4\n5\n6\n")
+        .write_lcov("package", "lib", "tests::a", &[("src/lib.rs", &[1, 2, 3])])
+        .write_lcov("package", "lib", "tests::b", &[("src/lib.rs", &[3, 4])])
+        .write_digests(&["src/lib.rs"]);
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--line", "src/lib.rs:2-3"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(vec!["tests::a", "tests::b"], shown_tests(&output.stdout));
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--line", "src/lib.rs:2-3", "--min-lines", "2"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(vec!["tests::a"], shown_tests(&output.stdout));
+}
+
+#[test]
+fn min_lines_with_diff() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n2\n3\n4\n5\n6\n")
+        .write_source(
+            "changes.diff",
+            "--- a/src/lib.rs\n+++ b/src/lib.rs\n@@ -2,2 +2,2 @@\n-2\n-3\n+two\n+three\n",
+        )
+        .write_lcov("package", "lib", "tests::a", &[("src/lib.rs", &[1, 2, 3])])
+        .write_lcov("package", "lib", "tests::b", &[("src/lib.rs", &[3, 4])])
+        .write_digests(&["src/lib.rs"]);
+
+    let output = fixture
+        .command()
+        .args([
+            "--no-run",
+            "--diff",
+            "--diff-file",
+            "changes.diff",
+            "--min-lines",
+            "2",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(vec!["tests::a"], shown_tests(&output.stdout));
+}
+
+#[test]
+fn minimize() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n2\n3\n4\n5\n6\n")
+        .write_lcov("package", "lib", "tests::a", &[("src/lib.rs", &[1, 2])])
+        .write_lcov("package", "lib", "tests::b", &[("src/lib.rs", &[2, 3])])
+        .write_lcov("package", "lib", "tests::c", &[("src/lib.rs", &[3, 4])])
+        .write_digests(&["src/lib.rs"]);
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--line", "src/lib.rs:1-4"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        vec!["tests::a", "tests::b", "tests::c"],
+        shown_tests(&output.stdout)
+    );
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--line", "src/lib.rs:1-4", "--minimize"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(vec!["tests::a", "tests::c"], shown_tests(&output.stdout));
+}
+
+#[test]
+fn minimize_with_diff() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n2\n3\n4\n5\n6\n")
+        .write_lcov("package", "lib", "tests::a", &[("src/lib.rs", &[1, 2])])
+        .write_lcov("package", "lib", "tests::b", &[("src/lib.rs", &[2, 3])])
+        .write_lcov("package", "lib", "tests::c", &[("src/lib.rs", &[3, 4])])
+        .write_digests(&["src/lib.rs"]);
+
+    let mut command = fixture.command();
+    command.args(["--no-run", "--diff", "--minimize"]);
+    let output = output_with_stdin(
+        command,
+        "--- a/src/lib.rs\n+++ b/src/lib.rs\n@@ -1,4 +1,1 @@\n-1\n-2\n-3\n-4\n+x\n",
+    );
+    assert!(output.status.success());
+    assert_eq!(vec!["tests::a", "tests::c"], shown_tests(&output.stdout));
+}
+
+#[test]
+fn not_covering() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n2\n3\n4\n5\n6\n")
+        .write_lcov("package", "lib", "tests::a", &[("src/lib.rs", &[1, 2])])
+        .write_lcov("package", "lib", "tests::b", &[("src/lib.rs", &[3, 4])])
+        .write_lcov("package", "lib", "tests::c", &[("src/lib.rs", &[4, 5])])
+        .write_lcov("package", "lib", "tests::d", &[])
+        .write_digests(&["src/lib.rs"]);
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--line", "src/lib.rs:2-3", "--not-covering"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(vec!["tests::c", "tests::d"], shown_tests(&output.stdout));
+}
+
+#[test]
+fn not_covering_with_diff_file() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n2\n3\n")
+        .write_source(
+            "changes.diff",
+            "--- a/src/lib.rs\n+++ b/src/lib.rs\n@@ -1,1 +1,1 @@\n-1\n+one\n",
+        )
+        .write_lcov("package", "lib", "tests::a", &[("src/lib.rs", &[1])])
+        .write_lcov("package", "lib", "tests::b", &[("src/lib.rs", &[2])])
+        .write_digests(&["src/lib.rs"]);
+
+    let output = fixture
+        .command()
+        .args([
+            "--no-run",
+            "--diff",
+            "--diff-file",
+            "changes.diff",
+            "--not-covering",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(vec!["tests::b"], shown_tests(&output.stdout));
+}
+
+#[test]
+fn source_only() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n2\n3\n")
+        .write_source("tests/it.rs", "1\n2\n3\n")
+        .write_lcov(
+            "package",
+            "it",
+            "it_works",
+            &[("src/lib.rs", &[1]), ("tests/it.rs", &[2])],
+        )
+        .write_lcov("package", "lib", "tests::a", &[("src/lib.rs", &[1, 2])])
+        .write_digests(&["src/lib.rs", "tests/it.rs"]);
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--line", "tests/it.rs:2"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(vec!["it_works"], shown_tests(&output.stdout));
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--line", "tests/it.rs:2", "--source-only"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(shown_tests(&output.stdout).is_empty());
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--line", "src/lib.rs:1", "--source-only"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(vec!["it_works", "tests::a"], shown_tests(&output.stdout));
+}
+
+#[test]
+fn test_prefix() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n2\n3\n")
+        .write_lcov("package", "lib", "my_mod::a", &[("src/lib.rs", &[1])])
+        .write_lcov("package", "lib", "my_mod::sub::b", &[])
+        .write_lcov("package", "lib", "my_module::c", &[("src/lib.rs", &[2])])
+        .write_lcov("package", "lib", "other::d", &[("src/lib.rs", &[3])])
+        .write_digests(&["src/lib.rs"]);
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--test-prefix", "my_mod::"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        vec!["my_mod::a", "my_mod::sub::b"],
+        shown_tests(&output.stdout)
+    );
+
+    // smoelius: Tests selected by prefix are combined with those selected by line.
+    let output = fixture
+        .command()
+        .args([
+            "--no-run",
+            "--test-prefix",
+            "my_mod::",
+            "--line",
+            "src/lib.rs:3",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        vec!["my_mod::a", "my_mod::sub::b", "other::d"],
+        shown_tests(&output.stdout)
+    );
+}
+
+#[test]
+fn order_by_coverage() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n2\n3\n4\n5\n6\n")
+        .write_lcov("package", "lib", "tests::a", &[("src/lib.rs", &[1])])
+        .write_lcov("package", "lib", "tests::b", &[("src/lib.rs", &[1, 2, 3])])
+        .write_lcov("package", "lib", "tests::c", &[("src/lib.rs", &[2, 3])])
+        .write_digests(&["src/lib.rs"]);
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--line", "src/lib.rs:1-3"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        vec!["tests::a", "tests::b", "tests::c"],
+        shown_tests(&output.stdout)
+    );
+
+    let output = fixture
+        .command()
+        .args([
+            "--no-run",
+            "--line",
+            "src/lib.rs:1-3",
+            "--order-by-coverage",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        vec!["tests::b", "tests::c", "tests::a"],
+        shown_tests(&output.stdout)
+    );
+}
+
+#[test]
+fn order_by_coverage_with_file() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n2\n3\n")
+        .write_lcov("package", "lib", "tests::a", &[("src/lib.rs", &[1])])
+        .write_lcov("package", "lib", "tests::b", &[("src/lib.rs", &[1, 2, 3])])
+        .write_digests(&["src/lib.rs"]);
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--file", "src/lib.rs", "--order-by-coverage"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(vec!["tests::b", "tests::a"], shown_tests(&output.stdout));
+}
+
+#[test]
+fn top() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n2\n3\n4\n5\n6\n")
+        .write_lcov("package", "lib", "tests::a", &[("src/lib.rs", &[1, 2])])
+        .write_lcov(
+            "package",
+            "lib",
+            "tests::b",
+            &[("src/lib.rs", &[1, 2, 3, 4])],
+        )
+        .write_lcov("package", "lib", "tests::c", &[("src/lib.rs", &[3])])
+        .write_lcov(
+            "package",
+            "lib",
+            "tests::d",
+            &[("src/lib.rs", &[2, 3, 4, 5, 6])],
+        )
+        .write_digests(&["src/lib.rs"]);
+
+    let output = fixture
+        .command()
+        .args(["--line", "src/lib.rs:1-4", "--top", "2"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        "4 package lib tests::b\n3 package lib tests::d\n",
+        std::str::from_utf8(&output.stdout).unwrap()
+    );
+}
+
+#[test]
+fn top_requires_line_specification() {
+    let fixture = Fixture::new();
+
+    let output = fixture.command().args(["--top", "2"]).output().unwrap();
+    assert!(!output.status.success());
+}
+
+#[test]
+fn top_with_file() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n2\n3\n")
+        .write_source("src/other.rs", "1\n")
+        .write_lcov("package", "lib", "tests::a", &[("src/lib.rs", &[1])])
+        .write_lcov("package", "lib", "tests::b", &[("src/lib.rs", &[1, 2, 3])])
+        .write_lcov("package", "lib", "tests::c", &[("src/other.rs", &[1])])
+        .write_digests(&["src/lib.rs", "src/other.rs"]);
+
+    let output = fixture
+        .command()
+        .args(["--file", "src/lib.rs", "--top", "1"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        "3 package lib tests::b\n",
+        std::str::from_utf8(&output.stdout).unwrap()
+    );
+}
+
+#[test]
+fn dedup_tests() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n")
+        .write_lcov("package", "lib", "tests::shared", &[("src/lib.rs", &[1])])
+        .write_lcov("package", "lib", "tests::unique", &[("src/lib.rs", &[1])])
+        .write_lcov(
+            "package",
+            "integration",
+            "tests::shared",
+            &[("src/lib.rs", &[1])],
+        )
+        .write_digests(&["src/lib.rs"]);
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--line", "src/lib.rs:1"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        vec!["tests::shared", "tests::shared", "tests::unique"],
+        shown_tests(&output.stdout)
+    );
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--line", "src/lib.rs:1", "--dedup-tests"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        vec!["tests::shared", "tests::unique"],
+        shown_tests(&output.stdout)
+    );
+    // smoelius: `integration` precedes `lib` alphabetically, so its `tests::shared` is kept.
+    let stdout = std::str::from_utf8(&output.stdout).unwrap();
+    assert!(
+        stdout.contains("--test integration -- --exact 'tests::shared'"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn exclude_test() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n2\n")
+        .write_lcov("package", "lib", "tests::fast", &[("src/lib.rs", &[1])])
+        .write_lcov("package", "lib", "tests::slow_a", &[("src/lib.rs", &[1])])
+        .write_lcov("package", "lib", "tests::slow_b", &[("src/lib.rs", &[1])])
+        .write_lcov("package", "lib", "other::slow_c", &[("src/lib.rs", &[1])])
+        .write_digests(&["src/lib.rs"]);
+
+    let output = fixture
+        .command()
+        .args([
+            "--no-run",
+            "--line",
+            "src/lib.rs:1",
+            "--exclude-test",
+            "tests::slow_*",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        vec!["other::slow_c", "tests::fast"],
+        shown_tests(&output.stdout)
+    );
+}
+
+#[test]
+fn test_filter() {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n2\n")
+        .write_lcov("package", "lib", "integration::a", &[("src/lib.rs", &[1])])
+        .write_lcov("package", "lib", "integration::b", &[("src/lib.rs", &[2])])
+        .write_lcov("package", "lib", "unit::a", &[("src/lib.rs", &[1])])
+        .write_digests(&["src/lib.rs"]);
+
+    let output = fixture
+        .command()
+        .args([
+            "--no-run",
+            "--line",
+            "src/lib.rs:1",
+            "--test-filter",
+            "integration::",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(vec!["integration::a"], shown_tests(&output.stdout));
+}
+
+fn max_tests_fixture() -> Fixture {
+    let fixture = Fixture::new();
+    fixture
+        .write_source("src/lib.rs", "1\n2\n")
+        .write_lcov("package", "lib", "tests::a", &[("src/lib.rs", &[1])])
+        .write_lcov("package", "lib", "tests::b", &[("src/lib.rs", &[1])])
+        .write_lcov("package", "lib", "tests::c", &[("src/lib.rs", &[1])])
+        .write_digests(&["src/lib.rs"]);
+    fixture
+}
+
+#[test]
+fn max_tests_not_exceeded() {
+    let fixture = max_tests_fixture();
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--line", "src/lib.rs:1", "--max-tests", "3"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        vec!["tests::a", "tests::b", "tests::c"],
+        shown_tests(&output.stdout)
+    );
+}
+
+#[test]
+fn max_tests_exceeded() {
+    let fixture = max_tests_fixture();
+
+    let output = fixture
+        .command()
+        .args(["--no-run", "--line", "src/lib.rs:1", "--max-tests", "2"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(shown_tests(&output.stdout).is_empty());
+    assert!(std::str::from_utf8(&output.stderr)
+        .unwrap()
+        .contains("3 tests were selected, more than --max-tests 2"));
+}
+
+#[test]
+fn max_tests_truncate() {
+    let fixture = max_tests_fixture();
+
+    let output = fixture
+        .command()
+        .args([
+            "--no-run",
+            "--line",
+            "src/lib.rs:1",
+            "--max-tests",
+            "2",
+            "--max-tests-truncate",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(vec!["tests::a", "tests::b"], shown_tests(&output.stdout));
+    assert!(std::str::from_utf8(&output.stderr).unwrap().contains(
+        "Warning: 3 tests were selected, more than --max-tests 2; running only the first 2"
+    ));
+}
+
+fn sample_fixture() -> Fixture {
+    let fixture = Fixture::new();
+    fixture.write_source("src/lib.rs", "1\n");
+    for i in 0..10 {
+        fixture.write_lcov(
+            "package",
+            "lib",
+            &format!("tests::t{i}"),
+            &[("src/lib.rs", &[1])],
+        );
+    }
+    fixture.write_digests(&["src/lib.rs"]);
+    fixture
+}
+
+fn sample(fixture: &Fixture, args: &[&str]) -> Vec<String> {
+    let output = fixture
+        .command()
+        .args(["--no-run", "--line", "src/lib.rs:1"])
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:#?}");
+    shown_tests(&output.stdout)
+}
+
+#[test]
+fn same_seed_same_sample() {
+    let fixture = sample_fixture();
+
+    let first = sample(&fixture, &["--sample", "3", "--seed", "1"]);
+    assert_eq!(3, first.len());
+    assert_eq!(first, sample(&fixture, &["--sample", "3", "--seed", "1"]));
+
+    let second = sample(&fixture, &["--sample", "3", "--seed", "2"]);
+    assert_eq!(3, second.len());
+    assert_ne!(first, second);
+}
+
+#[test]
+fn sample_percentage() {
+    let fixture = sample_fixture();
+
+    assert_eq!(5, sample(&fixture, &["--sample", "50%"]).len());
+    assert_eq!(1, sample(&fixture, &["--sample", "1%"]).len());
+    assert_eq!(10, sample(&fixture, &["--sample", "100%"]).len());
+    assert_eq!(10, sample(&fixture, &["--sample", "20"]).len());
+}
+
+#[test]
+fn invalid_sample() {
+    let fixture = sample_fixture();
+
+    for sample in ["0", "0%", "101%", "x"] {
+        let output = fixture
+            .command()
+            .args(["--no-run", "--line", "src/lib.rs:1", "--sample", sample])
+            .output()
+            .unwrap();
+        assert!(!output.status.success(), "{sample}");
+    }
+}